@@ -0,0 +1,65 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt::Write;
+
+/// The numbers of call sites in the `scaling_<N>_<implementation>` examples.
+const SCALING_COUNTS: [usize; 3] = [10, 100, 1000];
+
+/// The seed the scaling examples' call sites are generated from, unless
+/// `ALLOW_PIN_SCALING_SEED` is set.
+const SCALING_SEED: u64 = 0x416c6c6f77;
+
+/// The number of call sites in the `complex_<implementation>` examples.
+const COMPLEX_COUNT: usize = 100;
+
+/// The seed the complex examples' call sites are generated from. Unlike the
+/// scaling seed, it cannot be overridden, so the complex examples stay
+/// comparable between builds.
+const COMPLEX_SEED: u64 = 0x436f6d706c6578;
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-env-changed=ALLOW_PIN_SCALING_SEED");
+
+    // The examples define their own `_start`, which collides with the C
+    // runtime's when they are linked for the host (e.g. by `cargo test`). They
+    // are never run on the host, so leave the C startup files out.
+    let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    if !matches!(arch.as_str(), "arm" | "riscv32" | "riscv64") {
+        println!("cargo::rustc-link-arg-examples=-nostartfiles");
+    }
+
+    let seed = match std::env::var("ALLOW_PIN_SCALING_SEED") {
+        Ok(seed) => seed.parse().expect("ALLOW_PIN_SCALING_SEED must be a u64"),
+        Err(_) => SCALING_SEED,
+    };
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let calls = generate_calls(&mut ChaCha8Rng::seed_from_u64(COMPLEX_SEED), COMPLEX_COUNT);
+    std::fs::write(out_dir.join("complex.rs"), calls).unwrap();
+    for count in SCALING_COUNTS {
+        // Each count gets its own generator, so adding a count does not change
+        // the call sites of the others.
+        let calls = generate_calls(&mut ChaCha8Rng::seed_from_u64(seed ^ count as u64), count);
+        std::fs::write(out_dir.join(format!("scaling_{count}.rs")), calls).unwrap();
+    }
+}
+
+/// Generates the body of a complex or scaling example's `_start`: a block that
+/// passes a buffer through `count` calls to `app`, each with a random driver
+/// number, RO and RW allow numbers, and RW buffer length.
+fn generate_calls(rng: &mut ChaCha8Rng, count: usize) -> String {
+    let mut calls = format!("{{\n    let buffer = [0; {}];\n", rng.random_range(1..100));
+    for _ in 0..count {
+        writeln!(
+            calls,
+            "    let buffer: [_; {}] = app::<{}, {}, {}, _, _>(buffer)?;",
+            rng.random_range(1..100),
+            rng.random_range(0..10000),
+            rng.random_range(0..10),
+            rng.random_range(0..10),
+        )
+        .unwrap();
+    }
+    calls.push_str("    let _ = buffer;\n}\n");
+    calls
+}
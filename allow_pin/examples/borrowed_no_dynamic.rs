@@ -0,0 +1,45 @@
+//! A library that shares slices it was handed by the app, rather than data it
+//! owns, using `BorrowedBuffer`.
+
+#![no_main]
+#![no_std]
+
+use allow_pin::{DriverNum, ErrorCode, command, no_dynamic::*};
+use core::cell::Cell;
+use core::pin::pin;
+
+/// Using the specified driver number, write `ro_data` to the given RO Allow ID
+/// and read into `rw_data` from the given RW Allow ID.
+fn api<const DRIVER_NUM: u32, const RO_BUFFER: u32, const RW_BUFFER: u32>(
+    ro_data: &[u8],
+    rw_data: &mut [u8],
+) -> Result<(), ErrorCode> {
+    BorrowedBuffer::<AllowRo<DriverNum<DRIVER_NUM>, RO_BUFFER>>::with(ro_data, |ro_buffer| {
+        BorrowedBuffer::<AllowRw<DriverNum<DRIVER_NUM>, RW_BUFFER>>::with_mut(
+            rw_data,
+            |rw_buffer| {
+                ro_buffer.allow()?;
+                rw_buffer.allow()?;
+                let done =
+                    pin!(Subscription::<Cell<Option<[u32; 3]>>, DRIVER_NUM, RW_BUFFER>::default());
+                done.as_ref().subscribe()?;
+                // Dummy command invocation to clobber registers and add an
+                // error return path.
+                command(DRIVER_NUM, RO_BUFFER, RW_BUFFER, 0)?;
+                // Yield goes here. Unallows happen when the closures return.
+                Ok(())
+            },
+        )
+    })
+}
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut a = [0; 40];
+    let mut b = [0; 81];
+    let mut c = [0; 70];
+    api::<8198, 9, 9>(&a, &mut b)?;
+    api::<8388, 7, 0>(&b, &mut c)?;
+    api::<9167, 3, 2>(&c, &mut a)?;
+    Ok(())
+}
@@ -0,0 +1,23 @@
+//! Not an app: keeps one of each `full_dynamic` buffer type in `.bss`, so the
+//! size reports show the RAM each one takes as the size of its symbol. Built
+//! with and without the `unpacked_share_info` feature, it measures what packing
+//! the share info saves.
+
+#![no_main]
+#![no_std]
+
+use allow_pin::full_dynamic::*;
+use core::mem::MaybeUninit;
+
+#[used]
+#[unsafe(no_mangle)]
+static mut BUFFER: MaybeUninit<Buffer<[u8; 0]>> = MaybeUninit::uninit();
+#[used]
+#[unsafe(no_mangle)]
+static mut STATIC_BUFFER: MaybeUninit<StaticBuffer<[u8]>> = MaybeUninit::uninit();
+#[used]
+#[unsafe(no_mangle)]
+static mut BORROWED_BUFFER: MaybeUninit<BorrowedBuffer<'static>> = MaybeUninit::uninit();
+
+#[unsafe(no_mangle)]
+fn _start() {}
@@ -0,0 +1,63 @@
+//! An example that makes many different system calls with many different buffer
+//! sizes (using generics and lots of random numbers). Intended to test how the
+//! code size scales to large apps that use many drivers. The 100 calls are
+//! generated by build.rs into `complex.rs`, the same for every implementation.
+
+#![no_main]
+#![no_std]
+
+use allow_pin::{DriverNum, ErrorCode, command, share::*};
+use core::cell::Cell;
+
+/// Using the specified driver number, write the given buffer to the given RO
+/// Allow ID and read the given buffer from the given RW Allow ID, returning its
+/// contents. This intentionally does not know the buffer sizes at compile time,
+/// as it's simulating an API working on data provided by an external crate.
+fn api<const DRIVER_NUM: u32, const RO_BUFFER: u32, const RW_BUFFER: u32>(
+    ro_buffer: &[u8],
+    rw_buffer: &mut [u8],
+) -> Result<(), ErrorCode> {
+    let done: Cell<Option<[u32; 3]>> = Cell::new(None);
+    scope::<
+        (
+            AllowRo<DriverNum<DRIVER_NUM>, RO_BUFFER>,
+            AllowRw<DriverNum<DRIVER_NUM>, RW_BUFFER>,
+            Subscribe<DRIVER_NUM, RW_BUFFER>,
+        ),
+        _,
+        _,
+    >(|handle| {
+        let (allow_ro, allow_rw, subscribe) = handle.split();
+        allow_ro.allow(ro_buffer)?;
+        allow_rw.allow(rw_buffer)?;
+        subscribe.subscribe(&done)?;
+        // Dummy command invocation to clobber registers and add an error return
+        // path.
+        command(DRIVER_NUM, RO_BUFFER, RW_BUFFER, 0)?;
+        // Yield goes here.
+        // The unallows are performed when the scope ends.
+        Ok(())
+    })
+}
+
+/// Pretend application-crate function that creates the allow buffers and then
+/// uses them with api().
+fn app<
+    const DRIVER_NUM: u32,
+    const RO_BUFFER: u32,
+    const RW_BUFFER: u32,
+    const RO_LEN: usize,
+    const RW_LEN: usize,
+>(
+    ro_data: [u8; RO_LEN],
+) -> Result<[u8; RW_LEN], ErrorCode> {
+    let mut rw_buffer = [0; RW_LEN];
+    api::<DRIVER_NUM, RO_BUFFER, RW_BUFFER>(&ro_data, &mut rw_buffer)?;
+    Ok(rw_buffer)
+}
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    include!(concat!(env!("OUT_DIR"), "/complex.rs"));
+    Ok(())
+}
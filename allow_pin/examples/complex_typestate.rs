@@ -0,0 +1,56 @@
+//! An example that makes many different system calls with many different buffer
+//! sizes (using generics and lots of random numbers). Intended to test how the
+//! code size scales to large apps that use many drivers. The 100 calls are
+//! generated by build.rs into `complex.rs`, the same for every implementation.
+
+#![no_main]
+#![no_std]
+
+use allow_pin::{DriverNum, ErrorCode, command, typestate::*};
+use core::cell::Cell;
+use core::pin::{Pin, pin};
+
+/// Using the specified driver number, write the given buffer to the given RO
+/// Allow ID and read the given buffer from the given RW Allow ID, returning its
+/// contents. This intentionally does not know the buffer sizes at compile time,
+/// as it's simulating an API working on data provided by an external crate.
+fn api<const DRIVER_NUM: u32, const RO_BUFFER: u32, const RW_BUFFER: u32>(
+    ro_buffer: Pin<&mut Buffer<AllowRo<DriverNum<DRIVER_NUM>, RO_BUFFER>, [u8]>>,
+    rw_buffer: Pin<&mut Buffer<AllowRw<DriverNum<DRIVER_NUM>, RW_BUFFER>, [u8]>>,
+) -> Result<(), ErrorCode> {
+    let ro_buffer = ro_buffer.handle().share().map_err(|(_, error)| error)?;
+    let rw_buffer = rw_buffer.handle().share().map_err(|(_, error)| error)?;
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>, DRIVER_NUM, RW_BUFFER>::default());
+    let _subscribed = done.as_mut().subscribe()?;
+    // Dummy command invocation to clobber registers and add an error return
+    // path.
+    command(DRIVER_NUM, RO_BUFFER, RW_BUFFER, 0)?;
+    // Yield goes here.
+    // Perform unallows.
+    ro_buffer.unshare();
+    rw_buffer.unshare();
+    Ok(())
+}
+
+/// Pretend application-crate function that creates the allow buffers and then
+/// uses them with api().
+fn app<
+    const DRIVER_NUM: u32,
+    const RO_BUFFER: u32,
+    const RW_BUFFER: u32,
+    const RO_LEN: usize,
+    const RW_LEN: usize,
+>(
+    ro_data: [u8; RO_LEN],
+) -> Result<[u8; RW_LEN], ErrorCode> {
+    let mut ro_buffer = pin!(Buffer::<_, [u8; RO_LEN]>::from(ro_data));
+    let mut rw_buffer = pin!(Buffer::from([0; RW_LEN]));
+    api::<DRIVER_NUM, RO_BUFFER, RW_BUFFER>(ro_buffer.as_mut(), rw_buffer.as_mut())?;
+    Ok(*rw_buffer.handle().buffer())
+}
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    include!(concat!(env!("OUT_DIR"), "/complex.rs"));
+    Ok(())
+}
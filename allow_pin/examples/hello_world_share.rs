@@ -0,0 +1,22 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, console, share::*};
+use core::cell::Cell;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    // I wanted to use "Hello, world!\n" but long strings resulted in memcpy
+    // being included, which is hundreds of bytes and throws off the code size
+    // comparison.
+    let buffer = *b"hi";
+    let done: Cell<Option<[u32; 3]>> = Cell::new(None);
+    scope::<(console::WriteBuffer, Subscribe<0x1, 0x1>), _, _>(|handle| {
+        let (allow_ro, subscribe) = handle.split();
+        allow_ro.allow(&buffer)?;
+        subscribe.subscribe(&done)?;
+        command(0x1, 0x1, 14, 0)?;
+        // Wait for an upcall here.
+        Ok(())
+    })
+}
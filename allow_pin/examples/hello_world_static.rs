@@ -2,6 +2,7 @@
 #![no_std]
 
 use allow_pin::{command, full_dynamic::*};
+use core::cell::Cell;
 use core::pin::pin;
 
 static WELCOME: [u8; 2] = *b"hi";
@@ -9,6 +10,8 @@ static WELCOME: [u8; 2] = *b"hi";
 #[unsafe(no_mangle)]
 fn _start() -> Result<(), u32> {
     let mut buffer = pin!(StaticBuffer::<[u8; _]>::from(&WELCOME));
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>>::default());
+    done.as_mut().subscribe(0x1, 0x1)?;
     buffer.as_mut().allow(0x1, 0x1)?;
 
     command(0x1, 0x1, 14, 0)?;
@@ -0,0 +1,24 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, console, typestate::*};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    // I wanted to use "Hello, world!\n" but long strings resulted in memcpy
+    // being included, which is hundreds of bytes and throws off the code size
+    // comparison.
+    let mut buffer = pin!(Buffer::<console::WriteBuffer, [u8; _]>::from(*b"hi"));
+    let _shared = buffer
+        .as_mut()
+        .handle()
+        .share()
+        .map_err(|(_, error)| error)?;
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x1, 0x1>::default());
+    let _subscribed = done.as_mut().subscribe()?;
+    command(0x1, 0x1, 14, 0)?;
+    // Wait for an upcall here.
+    Ok(())
+}
@@ -0,0 +1,21 @@
+//! `print_rng_manual`, using `blocking::allow_command_wait` for each
+//! operation.
+
+#![no_main]
+#![no_std]
+
+use allow_pin::blocking::allow_command_wait;
+use allow_pin::{console, no_dynamic::*, rng};
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    // Read some random data.
+    let rng_buffer = pin!(Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    let (_, random) = allow_command_wait(rng_buffer, 0x40001, 0x0, 0x1, 8, 0)?;
+
+    // Write that data to the console.
+    let console_buffer = pin!(Buffer::<console::WriteBuffer, [u8; 8]>::from(*random));
+    allow_command_wait(console_buffer.into_ref(), 0x1, 0x1, 0x1, 8, 0)?;
+    Ok(())
+}
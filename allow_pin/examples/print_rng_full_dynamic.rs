@@ -0,0 +1,11 @@
+//! `scenarios/print_rng.rs` with `full_dynamic`.
+
+#![no_main]
+#![no_std]
+
+include!("scenarios/print_rng.rs");
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    Ok(app::<allow_pin::full_dynamic::FullDynamicImpl>()?)
+}
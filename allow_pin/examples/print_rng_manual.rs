@@ -0,0 +1,34 @@
+//! `print_rng_no_dynamic`, waiting for each upcall by writing out the sequence
+//! `blocking::allow_command_wait` performs, to compare against
+//! `print_rng_blocking`.
+
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, console, no_dynamic::*, rng, yield_wait_for};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    // Read some random data.
+    let mut rng_buffer = pin!(Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    rng_buffer.as_mut().allow()?;
+    let rng_done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    rng_done.as_ref().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    yield_wait_for(0x40001, 0x0);
+    rng_done.as_ref().unsubscribe();
+
+    // Retrieve the buffer from the RNG, then write that data to the console.
+    let console_buffer = pin!(Buffer::<console::WriteBuffer, [u8; 8]>::from(
+        *rng_buffer.as_mut().buffer_mut()
+    ));
+    console_buffer.as_ref().allow_ro()?;
+    let console_done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x1, 0x1>::default());
+    console_done.as_ref().subscribe()?;
+    command(0x1, 0x1, 8, 0)?;
+    yield_wait_for(0x1, 0x1);
+    console_done.as_ref().unsubscribe();
+    Ok(())
+}
@@ -0,0 +1,31 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, console, rng, share::*};
+use core::cell::Cell;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    // Read some random data.
+    let mut rng_buffer = [0; 8];
+    let rng_done: Cell<Option<[u32; 3]>> = Cell::new(None);
+    scope::<(rng::Buffer, Subscribe<0x40001, 0x0>), _, _>(|handle| {
+        let (allow_rw, subscribe) = handle.split();
+        allow_rw.allow(&mut rng_buffer)?;
+        subscribe.subscribe(&rng_done)?;
+        command(0x40001, 0x1, 8, 0)?;
+        // Wait for an upcall here.
+        Ok::<_, u32>(())
+    })?;
+
+    // The scope unallowed the RNG buffer, so write that data to the console.
+    let console_done: Cell<Option<[u32; 3]>> = Cell::new(None);
+    scope::<(console::WriteBuffer, Subscribe<0x1, 0x1>), _, _>(|handle| {
+        let (allow_ro, subscribe) = handle.split();
+        allow_ro.allow(&rng_buffer)?;
+        subscribe.subscribe(&console_done)?;
+        command(0x1, 0x1, 8, 0)?;
+        // Wait for an upcall here.
+        Ok(())
+    })
+}
@@ -0,0 +1,36 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, console, rng, typestate::*};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    // Read some random data.
+    let mut rng_buffer = pin!(Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    let rng_shared = rng_buffer
+        .as_mut()
+        .handle()
+        .share()
+        .map_err(|(_, error)| error)?;
+    let mut rng_done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    let _rng_subscribed = rng_done.as_mut().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+
+    // Retrieve the buffer from the RNG, then write that data to the console.
+    let mut console_buffer = pin!(Buffer::<console::WriteBuffer, [u8; 8]>::from(
+        *rng_shared.unshare().buffer()
+    ));
+    let _console_shared = console_buffer
+        .as_mut()
+        .handle()
+        .share()
+        .map_err(|(_, error)| error)?;
+    let mut console_done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x1, 0x1>::default());
+    let _console_subscribed = console_done.as_mut().subscribe()?;
+    command(0x1, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    Ok(())
+}
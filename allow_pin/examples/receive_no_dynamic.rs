@@ -0,0 +1,23 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, no_dynamic::*, rng};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut buffer = pin!(Buffer::<rng::Buffer, [u8; 256]>::from([0; 256]));
+    buffer.as_mut().allow()?;
+    let done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    done.as_ref().subscribe()?;
+    command(0x40001, 0x1, 256, 0)?;
+    // Wait for an upcall here.
+    let len = done
+        .as_ref()
+        .upcall()
+        .get()
+        .map_or(0, |[_, len, _]| len as usize);
+    let _random = &buffer.as_mut().buffer_mut()[..len.min(256)];
+    Ok(())
+}
@@ -0,0 +1,18 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::uninit::{Buffer, Subscription};
+use allow_pin::{command, rng};
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut buffer = pin!(Buffer::<rng::Buffer, 256>::default());
+    let done = pin!(Subscription::<rng::Rng, 0x0, 1>::default());
+    buffer.as_mut().allow(done.as_ref())?;
+    done.as_ref().subscribe()?;
+    command(0x40001, 0x1, 256, 0)?;
+    // Wait for an upcall here.
+    let _random = buffer.as_mut().unallow(done.as_ref());
+    Ok(())
+}
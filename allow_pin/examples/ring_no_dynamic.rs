@@ -0,0 +1,25 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::ring::BufferRing;
+use allow_pin::{command, no_dynamic::*, rng};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut ring = pin!(BufferRing::<rng::Buffer, [u8; 8], 3>::default());
+    ring.as_mut().start()?;
+    let done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    done.as_ref().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    ring.as_mut().rotate()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    ring.as_mut().rotate()?;
+    // Both chunks are processed after the fact, while a third buffer is shared.
+    let _random1 = ring.as_mut().consume(|buffer| *buffer);
+    let _random2 = ring.consume(|buffer| *buffer);
+    Ok(())
+}
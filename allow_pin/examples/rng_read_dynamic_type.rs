@@ -1,15 +1,11 @@
+//! `scenarios/rng_read.rs` with `dynamic_type`.
+
 #![no_main]
 #![no_std]
 
-use allow_pin::{command, dynamic_type::*};
-use core::pin::pin;
+include!("scenarios/rng_read.rs");
 
 #[unsafe(no_mangle)]
 fn _start() -> Result<(), u32> {
-    let mut buffer = pin!(Buffer::<[u8; 8], 0x40001, 0x0>::from([0; 8]));
-    buffer.as_mut().allow(DynamicType::Rw)?;
-    command(0x40001, 0x1, 8, 0)?;
-    // Wait for an upcall here.
-    let _random = *buffer.unallow();
-    Ok(())
+    Ok(app::<allow_pin::dynamic_type::DynamicTypeImpl>()?)
 }
@@ -0,0 +1,21 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, rng, share::*};
+use core::cell::Cell;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut buffer = [0; 8];
+    let done: Cell<Option<[u32; 3]>> = Cell::new(None);
+    scope::<(rng::Buffer, Subscribe<0x40001, 0x0>), _, _>(|handle| {
+        let (allow_rw, subscribe) = handle.split();
+        allow_rw.allow(&mut buffer)?;
+        subscribe.subscribe(&done)?;
+        command(0x40001, 0x1, 8, 0)?;
+        // Wait for an upcall here.
+        Ok::<_, u32>(())
+    })?;
+    let _random = buffer;
+    Ok(())
+}
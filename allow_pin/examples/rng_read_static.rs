@@ -0,0 +1,25 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, full_dynamic::*};
+use core::cell::Cell;
+use core::pin::pin;
+
+static mut RANDOM: [u8; 8] = [0; 8];
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    // SAFETY: _start only runs once, so this is the only reference to RANDOM.
+    let random = &raw mut RANDOM;
+    let random = unsafe { &mut *random };
+    let mut buffer = pin!(StaticBuffer::<[u8; _]>::from(random));
+    buffer.as_mut().allow(0x40001, 0x0)?;
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>>::default());
+    done.as_mut().subscribe(0x40001, 0x0)?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    if let Some(StaticRef::Rw(random)) = buffer.as_mut().take() {
+        let _random = *random;
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, rng, typestate::*};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut buffer = pin!(Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    let shared = buffer
+        .as_mut()
+        .handle()
+        .share()
+        .map_err(|(_, error)| error)?;
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    let _subscribed = done.as_mut().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let _random = *shared.unshare().buffer();
+    Ok(())
+}
@@ -0,0 +1,52 @@
+// The `complex` example with a generated number of call sites, for measuring
+// how code size scales with the number of drivers an app uses. Each
+// `scaling_<N>_full_dynamic` example includes this file, and runs the `N` calls
+// build.rs generated into `scaling_<N>.rs` (see `generate_calls`), which are
+// the same for every implementation.
+
+use allow_pin::{DriverNum, ErrorCode, command, full_dynamic::*};
+use core::cell::Cell;
+use core::pin::{Pin, pin};
+
+/// Using the specified driver number, write the given buffer to the given RO
+/// Allow ID and read the given buffer from the given RW Allow ID, returning its
+/// contents. This intentionally does not know the buffer sizes at compile time,
+/// as it's simulating an API working on data provided by an external crate.
+fn api<const DRIVER_NUM: u32, const RO_BUFFER: u32, const RW_BUFFER: u32>(
+    mut ro_buffer: Pin<&mut Buffer<[u8]>>,
+    mut rw_buffer: Pin<&mut Buffer<[u8]>>,
+) -> Result<(), ErrorCode> {
+    ro_buffer
+        .as_mut()
+        .allow_slot::<AllowRo<DriverNum<DRIVER_NUM>, RO_BUFFER>>()?;
+    rw_buffer
+        .as_mut()
+        .allow_slot::<AllowRw<DriverNum<DRIVER_NUM>, RW_BUFFER>>()?;
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>>::default());
+    done.as_mut().subscribe(DRIVER_NUM, RW_BUFFER)?;
+    // Dummy command invocation to clobber registers and add an error return
+    // path.
+    command(DRIVER_NUM, RO_BUFFER, RW_BUFFER, 0)?;
+    // Yield goes here.
+    // Perform unallows.
+    ro_buffer.unallow();
+    rw_buffer.unallow();
+    Ok(())
+}
+
+/// Pretend application-crate function that creates the allow buffers and then
+/// uses them with api().
+fn app<
+    const DRIVER_NUM: u32,
+    const RO_BUFFER: u32,
+    const RW_BUFFER: u32,
+    const RO_LEN: usize,
+    const RW_LEN: usize,
+>(
+    ro_data: [u8; RO_LEN],
+) -> Result<[u8; RW_LEN], ErrorCode> {
+    let ro_buffer = pin!(Buffer::<[u8; RO_LEN]>::from(ro_data));
+    let mut rw_buffer = pin!(Buffer::from([0; RW_LEN]));
+    api::<DRIVER_NUM, RO_BUFFER, RW_BUFFER>(ro_buffer, rw_buffer.as_mut())?;
+    Ok(*rw_buffer.unallow())
+}
@@ -0,0 +1,12 @@
+//! `scaling/no_dynamic.rs` with 10 generated call sites.
+
+#![no_main]
+#![no_std]
+
+include!("scaling/no_dynamic.rs");
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    include!(concat!(env!("OUT_DIR"), "/scaling_10.rs"));
+    Ok(())
+}
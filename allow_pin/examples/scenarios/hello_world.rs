@@ -0,0 +1,21 @@
+// Writes a short string to the console. Each `hello_world_<implementation>`
+// example (other than `share`, `static` and `typestate`, whose APIs differ)
+// includes this file and runs `app` with its implementation.
+
+use allow_pin::{ErrorCode, Implementation, command, console};
+use allow_pin::{AllowBuffer as _, AllowSubscription as _};
+use core::cell::Cell;
+use core::pin::pin;
+
+fn app<I: Implementation>() -> Result<(), ErrorCode> {
+    // I wanted to use "Hello, world!\n" but long strings resulted in memcpy
+    // being included, which is hundreds of bytes and throws off the code size
+    // comparison.
+    let mut buffer = pin!(I::Buffer::<console::WriteBuffer, [u8; _]>::from(*b"hi"));
+    buffer.as_mut().allow()?;
+    let mut done = pin!(I::Subscription::<Cell<Option<[u32; 3]>>, 0x1, 0x1>::default());
+    done.as_mut().subscribe()?;
+    command(0x1, 0x1, 14, 0)?;
+    // Wait for an upcall here.
+    Ok(())
+}
@@ -0,0 +1,30 @@
+// Reads some random data and writes it to the console. Each
+// `print_rng_<implementation>` example (other than `share` and `typestate`,
+// whose APIs differ, and `blocking` and `manual`, which also wait for the
+// upcalls) includes this file and runs `app` with its implementation.
+
+use allow_pin::{AllowBuffer as _, AllowSubscription as _};
+use allow_pin::{ErrorCode, Implementation, command, console, rng};
+use core::cell::Cell;
+use core::pin::pin;
+
+fn app<I: Implementation>() -> Result<(), ErrorCode> {
+    // Read some random data.
+    let mut rng_buffer = pin!(I::Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    rng_buffer.as_mut().allow()?;
+    let mut rng_done = pin!(I::Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    rng_done.as_mut().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+
+    // Retrieve the buffer from the RNG, then write that data to the console.
+    let console_buffer = pin!(I::Buffer::<console::WriteBuffer, [u8; 8]>::from(
+        *rng_buffer.unallow()
+    ));
+    console_buffer.allow()?;
+    let mut console_done = pin!(I::Subscription::<Cell<Option<[u32; 3]>>, 0x1, 0x1>::default());
+    console_done.as_mut().subscribe()?;
+    command(0x1, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    Ok(())
+}
@@ -0,0 +1,19 @@
+// Reads some random data. Each `rng_read_<implementation>` example (other than
+// `share`, `static` and `typestate`, whose APIs differ) includes this file and
+// runs `app` with its implementation.
+
+use allow_pin::{AllowBuffer as _, AllowSubscription as _};
+use allow_pin::{ErrorCode, Implementation, command, rng};
+use core::cell::Cell;
+use core::pin::pin;
+
+fn app<I: Implementation>() -> Result<(), ErrorCode> {
+    let mut buffer = pin!(I::Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    buffer.as_mut().allow()?;
+    let mut done = pin!(I::Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    done.as_mut().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let _random = *buffer.unallow();
+    Ok(())
+}
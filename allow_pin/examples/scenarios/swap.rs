@@ -0,0 +1,84 @@
+// Receives two chunks of random data, swapping between two buffers so one is
+// always shared. Each `swap_<implementation>` example (other than `share` and
+// `typestate`, whose APIs differ) includes this file and runs `app` with its
+// implementation.
+
+use allow_pin::{AllowBuffer, AllowSlot, AllowSubscription as _};
+use allow_pin::{ErrorCode, Implementation, command, rng};
+use core::cell::Cell;
+use core::pin::{Pin, pin};
+
+// Over-simplified streaming process client just to prove we can make streaming
+// process buffers work. This ignores the streaming process slice header; see
+// `allow_pin::streaming` for a receiver that parses it.
+struct StreamingReceiveSlice<I: Implementation, S: AllowSlot, const LEN: usize> {
+    // Two buffers to stream RNG data from. Both buffer_a and buffer_b are
+    // structurally pinned fields.
+    buffer_a: I::Buffer<S, [u8; LEN]>,
+    buffer_b: I::Buffer<S, [u8; LEN]>,
+
+    share_status: ShareStatus,
+}
+
+impl<I: Implementation, S: AllowSlot, const LEN: usize> Default
+    for StreamingReceiveSlice<I, S, LEN>
+{
+    fn default() -> StreamingReceiveSlice<I, S, LEN> {
+        StreamingReceiveSlice {
+            buffer_a: [0; LEN].into(),
+            buffer_b: [0; LEN].into(),
+            share_status: ShareStatus::None,
+        }
+    }
+}
+
+impl<I: Implementation, S: AllowSlot, const LEN: usize> StreamingReceiveSlice<I, S, LEN> {
+    // Shares the first buffer, starting the receive.
+    pub fn start(mut self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        unsafe { self.as_mut().map_unchecked_mut(|s| &mut s.buffer_a) }.allow()?;
+        unsafe { self.get_unchecked_mut() }.share_status = ShareStatus::A;
+        Ok(())
+    }
+
+    // Called repeatedly to swap the buffers and receive the next chunk.
+    pub fn next(self: Pin<&mut Self>) -> Result<&mut [u8; LEN], ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let [a, b] = unsafe {
+            [
+                Pin::new_unchecked(&mut this.buffer_a),
+                Pin::new_unchecked(&mut this.buffer_b),
+            ]
+        };
+        let (old, new, status) = match this.share_status {
+            ShareStatus::None => return Err(ErrorCode::Off),
+            ShareStatus::A => (a, b, ShareStatus::B),
+            ShareStatus::B => (b, a, ShareStatus::A),
+        };
+        let (out, result) = old.replace_with(new);
+        result?;
+        this.share_status = status;
+        Ok(out)
+    }
+}
+
+// Which buffer is currently shared. Not every implementation can report this,
+// so the struct tracks it itself.
+enum ShareStatus {
+    None,
+    A,
+    B,
+}
+
+fn app<I: Implementation>() -> Result<(), ErrorCode> {
+    let mut rng_stream = pin!(StreamingReceiveSlice::<I, rng::Buffer, 8>::default());
+    rng_stream.as_mut().start()?;
+    let mut done = pin!(I::Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    done.as_mut().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let _random1 = *rng_stream.as_mut().next()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let _random2 = *rng_stream.next()?;
+    Ok(())
+}
@@ -0,0 +1,24 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::streaming::StreamingReceiveSlice;
+use allow_pin::{command, no_dynamic::*, rng};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut rng_stream = pin!(StreamingReceiveSlice::<rng::Buffer, 16>::default());
+    rng_stream.as_mut().start()?;
+    let done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    done.as_ref().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let _random1 = rng_stream.as_mut().next()?.data.first().copied();
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let received = rng_stream.next()?;
+    let _random2 = received.data.first().copied();
+    let _dropped = received.exceeded;
+    Ok(())
+}
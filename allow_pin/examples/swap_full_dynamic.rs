@@ -0,0 +1,11 @@
+//! `scenarios/swap.rs` with `full_dynamic`.
+
+#![no_main]
+#![no_std]
+
+include!("scenarios/swap.rs");
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    Ok(app::<allow_pin::full_dynamic::FullDynamicImpl>()?)
+}
@@ -0,0 +1,40 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{DriverNum, command, share::*};
+use core::cell::Cell;
+
+// A scope cannot keep one buffer shared while the app reads the other, so
+// unlike the other swap examples this receives each chunk in its own scope,
+// leaving a gap between the chunks where no buffer is shared.
+fn receive<const LEN: usize, const DRIVER_NUM: u32, const BUFFER_NUM: u32>(
+    buffer: &mut [u8; LEN],
+) -> Result<(), u32> {
+    let done: Cell<Option<[u32; 3]>> = Cell::new(None);
+    scope::<
+        (
+            AllowRw<DriverNum<DRIVER_NUM>, BUFFER_NUM>,
+            Subscribe<DRIVER_NUM, BUFFER_NUM>,
+        ),
+        _,
+        _,
+    >(|handle| {
+        let (allow_rw, subscribe) = handle.split();
+        allow_rw.allow(buffer)?;
+        subscribe.subscribe(&done)?;
+        command(DRIVER_NUM, 0x1, LEN as u32, 0)?;
+        // Wait for an upcall here.
+        Ok(())
+    })
+}
+
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut buffer_a = [0; 8];
+    let mut buffer_b = [0; 8];
+    receive::<8, 0x40001, 0x0>(&mut buffer_a)?;
+    let _random1 = buffer_a;
+    receive::<8, 0x40001, 0x0>(&mut buffer_b)?;
+    let _random2 = buffer_b;
+    Ok(())
+}
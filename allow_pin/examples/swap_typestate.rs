@@ -0,0 +1,31 @@
+#![no_main]
+#![no_std]
+
+use allow_pin::{command, rng, typestate::*};
+use core::cell::Cell;
+use core::pin::pin;
+
+// The typestate guards borrow their buffers, so unlike the other swap examples
+// the two buffers cannot live in the same struct as the guard that shares
+// them. Instead, the share state is carried from one call to the next by the
+// guard that is returned.
+#[unsafe(no_mangle)]
+fn _start() -> Result<(), u32> {
+    let mut buffer_a = pin!(Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    let mut buffer_b = pin!(Buffer::<rng::Buffer, [u8; 8]>::from([0; 8]));
+    // Handles must be created before either buffer is shared.
+    let a = buffer_a.as_mut().handle();
+    let b = buffer_b.as_mut().handle();
+    let shared = a.share().map_err(|(_, error)| error)?;
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 0x40001, 0x0>::default());
+    let _subscribed = done.as_mut().subscribe()?;
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let (a, shared) = shared.replace_with(b).map_err(|(.., error)| error)?;
+    let _random1 = *a.buffer();
+    command(0x40001, 0x1, 8, 0)?;
+    // Wait for an upcall here.
+    let (b, _shared) = shared.replace_with(a).map_err(|(.., error)| error)?;
+    let _random2 = *b.buffer();
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Traits that the `no_dynamic`, `dynamic_type` and `full_dynamic` Buffers and
+//! Subscriptions all implement, so an app (or a test) can be written once,
+//! generic over `Implementation`, and instantiated for each of them with the
+//! marker type in its module (`no_dynamic::NoDynamicImpl`,
+//! `dynamic_type::DynamicTypeImpl` and `full_dynamic::FullDynamicImpl`).
+//!
+//! The traits only cover what all three can do with a typed allow slot and a
+//! const subscribe ID. Each implementation's own methods (such as
+//! `full_dynamic`'s runtime allow IDs) are still available on its types.
+
+use crate::*;
+
+/// A pinned buffer that can be shared with allow slot `S`.
+pub trait AllowBuffer<S: AllowSlot> {
+    type Data: FromBytes + IntoBytes + ?Sized;
+
+    /// Shares the buffer with `S`.
+    fn allow(self: Pin<&mut Self>) -> Result<(), ErrorCode>;
+
+    /// Unshares the buffer, if it may be shared, and returns it.
+    fn unallow(self: Pin<&mut Self>) -> &mut Self::Data;
+
+    /// Shares `other` in place of this buffer and returns this buffer, which is
+    /// unshared whether or not sharing `other` succeeded. By default this
+    /// unallows and then allows; implementations that can swap the buffers in
+    /// a single Allow call do so.
+    fn replace_with<'a>(
+        self: Pin<&'a mut Self>,
+        other: Pin<&mut Self>,
+    ) -> (&'a mut Self::Data, Result<(), ErrorCode>) {
+        let buffer = self.unallow();
+        (buffer, other.allow())
+    }
+}
+
+/// A pinned upcall registration for subscribe ID `SUBSCRIBE_NUM` of driver
+/// `DRIVER_NUM`.
+pub trait AllowSubscription<const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> {
+    type Upcall: Upcall;
+
+    fn subscribe(self: Pin<&mut Self>) -> Result<(), ErrorCode>;
+
+    fn unsubscribe(self: Pin<&mut Self>);
+
+    /// The kernel only accesses the upcall through a shared reference, so this
+    /// works while subscribed.
+    fn upcall(self: Pin<&Self>) -> &Self::Upcall;
+}
+
+/// One of the `Pin`-based implementations, naming its Buffer and Subscription
+/// types.
+pub trait Implementation {
+    type Buffer<S: AllowSlot, B: FromBytes + IntoBytes>: AllowBuffer<S, Data = B> + From<B>;
+    type Subscription<U: Default + Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>: AllowSubscription<DRIVER_NUM, SUBSCRIBE_NUM, Upcall = U>
+        + Default;
+}
@@ -0,0 +1,93 @@
+//! `AllowSet`, which shares several Buffers together: either all of them are
+//! allowed or none are. It works with any Buffer that implements
+//! `AllowBuffer`, so `no_dynamic`, `dynamic_type` and `full_dynamic` Buffers
+//! can all be members (a `dynamic_type` Buffer needs a typed slot, whose class
+//! the set allows it with).
+
+use crate::*;
+
+/// The Buffers in an `AllowSet`, which are shared with the allow slots in `S`.
+/// Implemented for tuples of pinned `AllowBuffer`s, with `S` the tuple of their
+/// slots.
+pub trait Members<S> {
+    /// Allows each Buffer in order. If one fails, the Buffers before it are
+    /// unallowed again.
+    fn allow_all(&mut self) -> Result<(), ErrorCode>;
+    fn unallow_all(&mut self);
+}
+
+// Implements Members for a tuple of pinned AllowBuffers.
+macro_rules! tuple_members {
+    ($($slot:ident $buffer:ident $index:tt),*) => {
+        impl<$($slot: AllowSlot, $buffer: AllowBuffer<$slot> + ?Sized),*> Members<($($slot,)*)>
+            for ($(Pin<&mut $buffer>,)*)
+        {
+            fn allow_all(&mut self) -> Result<(), ErrorCode> {
+                let mut allowed = 0;
+                let result = (|| {
+                    $(
+                        AllowBuffer::<$slot>::allow(self.$index.as_mut())?;
+                        allowed += 1;
+                    )*
+                    Ok(())
+                })();
+                if result.is_err() {
+                    $(
+                        if $index < allowed {
+                            AllowBuffer::<$slot>::unallow(self.$index.as_mut());
+                        }
+                    )*
+                }
+                result
+            }
+
+            fn unallow_all(&mut self) {
+                $(AllowBuffer::<$slot>::unallow(self.$index.as_mut());)*
+            }
+        }
+    };
+}
+
+tuple_members!(SA A 0, SB B 1);
+tuple_members!(SA A 0, SB B 1, SC C 2);
+tuple_members!(SA A 0, SB B 1, SC C 2, SD D 3);
+
+/// Several Buffers that are shared together: either all of them are allowed or
+/// none are, and all of them are unallowed when the set is released or
+/// dropped. Leaking the set leaves the Buffers shared until they are dropped.
+///
+/// A `full_dynamic` Buffer can be shared with any slot, so a set containing
+/// one names the slots: `AllowSet::<(Command, Response), _>::allow(...)`.
+pub struct AllowSet<S, M: Members<S>> {
+    _slots: PhantomData<S>,
+    members: Option<M>,
+}
+
+impl<S, M: Members<S>> AllowSet<S, M> {
+    /// Allows every Buffer in `members`, in order. On failure, the Buffers
+    /// that were allowed are unallowed and `members` is returned.
+    pub fn allow(mut members: M) -> Result<AllowSet<S, M>, (M, ErrorCode)> {
+        match members.allow_all() {
+            Ok(()) => Ok(AllowSet {
+                _slots: PhantomData,
+                members: Some(members),
+            }),
+            Err(error) => Err((members, error)),
+        }
+    }
+
+    /// Unallows every Buffer and returns them.
+    pub fn release(mut self) -> M {
+        let mut members = self.members.take().unwrap();
+        members.unallow_all();
+        members
+    }
+}
+
+impl<S, M: Members<S>> Drop for AllowSet<S, M> {
+    fn drop(&mut self) {
+        if let Some(members) = &mut self.members {
+            members.unallow_all();
+        }
+    }
+}
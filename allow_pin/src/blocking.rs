@@ -0,0 +1,125 @@
+//! A blocking helper for the sequence most simple apps need, built on
+//! `no_dynamic`: share a set of buffers, subscribe, issue a command, Yield-WaitFor
+//! the command's upcall, unsubscribe, and unshare the buffers. Yield-WaitFor
+//! returns the upcall's arguments instead of running the subscribed upcall, and
+//! does not run any other upcall while it waits.
+//! The examples that end in `_manual` and `_blocking` compare its code size
+//! against writing the sequence out by hand.
+//!
+//! If sharing a buffer or the command fails, the buffers that were already
+//! shared stay shared, and are unshared when they are next accessed or dropped,
+//! as with any `no_dynamic` Buffer. The subscription is always removed.
+
+use crate::no_dynamic::Buffer;
+use crate::*;
+
+/// Buffers that `allow_command_wait` shares for the duration of one command:
+/// a pinned RO `Buffer` by shared reference, any pinned `Buffer` by mutable
+/// reference, `()` for commands that take no buffers, or a pair of sets.
+pub trait BufferSet {
+    /// The buffers, returned once they have been unshared.
+    type Buffers;
+
+    fn allow(&mut self) -> Result<(), ErrorCode>;
+
+    /// Unshares the buffers and returns them.
+    fn unallow(self) -> Self::Buffers;
+}
+
+impl<'b, D: Driver, const NUM: u32, B: FromBytes + IntoBytes + ?Sized> BufferSet
+    for Pin<&'b Buffer<AllowRo<D, NUM>, B>>
+{
+    type Buffers = &'b B;
+
+    fn allow(&mut self) -> Result<(), ErrorCode> {
+        self.as_ref().allow_ro()
+    }
+
+    fn unallow(self) -> &'b B {
+        Buffer::unallow_ref(self);
+        self.buffer()
+    }
+}
+
+impl<'b, S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> BufferSet for Pin<&'b mut Buffer<S, B>> {
+    type Buffers = &'b mut B;
+
+    fn allow(&mut self) -> Result<(), ErrorCode> {
+        Buffer::allow(self.as_mut())
+    }
+
+    fn unallow(self) -> &'b mut B {
+        self.buffer_mut()
+    }
+}
+
+impl BufferSet for () {
+    type Buffers = ();
+
+    fn allow(&mut self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn unallow(self) {}
+}
+
+impl<A: BufferSet, B: BufferSet> BufferSet for (A, B) {
+    type Buffers = (A::Buffers, B::Buffers);
+
+    fn allow(&mut self) -> Result<(), ErrorCode> {
+        self.0.allow()?;
+        self.1.allow()
+    }
+
+    fn unallow(self) -> (A::Buffers, B::Buffers) {
+        (self.0.unallow(), self.1.unallow())
+    }
+}
+
+/// Shares `buffers`, subscribes to `subscribe_num`, runs command `command_num`
+/// with `arg0` and `arg1` (all on driver `driver_num`), and waits for the
+/// upcall. Returns the upcall's arguments and the unshared buffers.
+pub fn allow_command_wait<S: BufferSet>(
+    mut buffers: S,
+    driver_num: u32,
+    subscribe_num: u32,
+    command_num: u32,
+    arg0: u32,
+    arg1: u32,
+) -> Result<([u32; 3], S::Buffers), ErrorCode> {
+    buffers.allow()?;
+    let done = Cell::new(None);
+    let subscription = Subscribed::new(driver_num, subscribe_num, &done)?;
+    command(driver_num, command_num, arg0, arg1)?;
+    let args = yield_wait_for(driver_num, subscribe_num);
+    drop(subscription);
+    Ok((args, buffers.unallow()))
+}
+
+/// Unsubscribes when dropped, including on the error paths of
+/// `allow_command_wait`. It is created after the upcall it registers, so it is
+/// dropped first.
+struct Subscribed {
+    driver_num: u32,
+    subscribe_num: u32,
+}
+
+impl Subscribed {
+    fn new(
+        driver_num: u32,
+        subscribe_num: u32,
+        upcall: &Cell<Option<[u32; 3]>>,
+    ) -> Result<Subscribed, ErrorCode> {
+        unsafe { subscribe_inner(driver_num, subscribe_num, upcall) }?;
+        Ok(Subscribed {
+            driver_num,
+            subscribe_num,
+        })
+    }
+}
+
+impl Drop for Subscribed {
+    fn drop(&mut self) {
+        unsubscribe(self.driver_num, self.subscribe_num);
+    }
+}
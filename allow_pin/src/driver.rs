@@ -0,0 +1,109 @@
+//! Typed driver and allow IDs. A `Driver` names a driver number, and an allow
+//! slot (`AllowRo`, `AllowRw` or `AllowUserspaceReadable`) names one of its
+//! allow numbers together with the class of allow it accepts, so a buffer
+//! cannot be shared with the wrong class or on the wrong driver. Drivers give
+//! their slots names, such as `console::WriteBuffer`. `Allow` names an allow
+//! number without a class, for `dynamic_type`, which chooses the class when it
+//! allows.
+//!
+//! ```compile_fail
+//! # use allow_pin::no_dynamic::*;
+//! # use core::pin::pin;
+//! let buffer = pin!(Buffer::<allow_pin::rng::Buffer, [u8; 4]>::from([0; 4]));
+//! buffer.as_ref().allow_ro(); // Error: the RNG buffer is read-write.
+//! ```
+
+use crate::*;
+
+/// A Tock driver.
+pub trait Driver {
+    const NUM: u32;
+}
+
+/// A driver that is only known by its number, for drivers that do not have a
+/// descriptor type of their own.
+pub enum DriverNum<const NUM: u32> {}
+
+impl<const NUM: u32> Driver for DriverNum<NUM> {
+    const NUM: u32 = NUM;
+}
+
+/// A driver and one of its allow numbers.
+pub trait AllowNumber {
+    type Driver: Driver;
+    const NUM: u32;
+}
+
+/// An allow ID: a driver, one of its allow numbers, and the class of allow
+/// that number belongs to.
+pub trait AllowSlot: AllowNumber {
+    type Class: StaticType;
+}
+
+/// Allow number `NUM` of driver `D`, of any class.
+pub struct Allow<D: Driver, const NUM: u32>(PhantomData<D>);
+
+/// Read-only allow number `NUM` of driver `D`.
+pub struct AllowRo<D: Driver, const NUM: u32>(PhantomData<D>);
+/// Read-write allow number `NUM` of driver `D`.
+pub struct AllowRw<D: Driver, const NUM: u32>(PhantomData<D>);
+/// Userspace-readable allow number `NUM` of driver `D`.
+pub struct AllowUserspaceReadable<D: Driver, const NUM: u32>(PhantomData<D>);
+
+impl<D: Driver, const NUM: u32> AllowNumber for Allow<D, NUM> {
+    type Driver = D;
+    const NUM: u32 = NUM;
+}
+
+impl<D: Driver, const NUM: u32> AllowNumber for AllowRo<D, NUM> {
+    type Driver = D;
+    const NUM: u32 = NUM;
+}
+
+impl<D: Driver, const NUM: u32> AllowSlot for AllowRo<D, NUM> {
+    type Class = StaticRo;
+}
+
+impl<D: Driver, const NUM: u32> AllowNumber for AllowRw<D, NUM> {
+    type Driver = D;
+    const NUM: u32 = NUM;
+}
+
+impl<D: Driver, const NUM: u32> AllowSlot for AllowRw<D, NUM> {
+    type Class = StaticRw;
+}
+
+impl<D: Driver, const NUM: u32> AllowNumber for AllowUserspaceReadable<D, NUM> {
+    type Driver = D;
+    const NUM: u32 = NUM;
+}
+
+impl<D: Driver, const NUM: u32> AllowSlot for AllowUserspaceReadable<D, NUM> {
+    type Class = StaticUserspaceReadable;
+}
+
+pub mod console {
+    use super::*;
+
+    pub enum Console {}
+
+    impl Driver for Console {
+        const NUM: u32 = 0x1;
+    }
+
+    /// Data to write to the console.
+    pub type WriteBuffer = AllowRo<Console, 0x1>;
+}
+
+pub mod rng {
+    use super::*;
+
+    pub enum Rng {}
+
+    impl Driver for Rng {
+        const NUM: u32 = 0x40001;
+    }
+
+    /// Receives random bytes.
+    pub type Buffer = AllowRw<Rng, 0x0>;
+}
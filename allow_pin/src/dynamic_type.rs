@@ -1,21 +1,31 @@
-//! An allow buffer that tracks RO vs RW at runtime but which has a const ID.
+//! An allow buffer that tracks RO vs RW at runtime but which has a const ID. The
+//! class is chosen each time the buffer is allowed, so the slot `S` only names
+//! the driver and allow number (`Allow`, or a typed slot whose class is
+//! ignored), and one set of methods serves every class.
 
+use crate::view::{self, View, ViewMut};
 use crate::*;
+use core::pin::pin;
+use core::ptr;
+use zerocopy::KnownLayout;
 
-pub use crate::DynamicType;
-pub type ErrorCode = u32;
+pub use crate::{
+    Allow, AllowNumber, AllowRo, AllowRw, AllowSlot, AllowUserspaceReadable, Driver, DynamicType,
+    ErrorCode,
+};
 
-pub struct Buffer<B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM: u32> {
+/// A buffer that can be shared with allow number `S`.
+pub struct Buffer<S: AllowNumber, B: FromBytes + IntoBytes + ?Sized> {
+    _slot: PhantomData<S>,
     _pinned: PhantomPinned,
     shared: Option<DynamicType>,
     buffer: B,
 }
 
-impl<B: Default + FromBytes + IntoBytes, const DRIVER_NUM: u32, const BUFFER_NUM: u32> Default
-    for Buffer<B, DRIVER_NUM, BUFFER_NUM>
-{
-    fn default() -> Buffer<B, DRIVER_NUM, BUFFER_NUM> {
+impl<S: AllowNumber, B: Default + FromBytes + IntoBytes> Default for Buffer<S, B> {
+    fn default() -> Buffer<S, B> {
         Buffer {
+            _slot: PhantomData,
             _pinned: PhantomPinned,
             shared: None,
             buffer: Default::default(),
@@ -23,11 +33,10 @@ impl<B: Default + FromBytes + IntoBytes, const DRIVER_NUM: u32, const BUFFER_NUM
     }
 }
 
-impl<B: FromBytes + IntoBytes, const DRIVER_NUM: u32, const BUFFER_NUM: u32> From<B>
-    for Buffer<B, DRIVER_NUM, BUFFER_NUM>
-{
-    fn from(buffer: B) -> Buffer<B, DRIVER_NUM, BUFFER_NUM> {
+impl<S: AllowNumber, B: FromBytes + IntoBytes> From<B> for Buffer<S, B> {
+    fn from(buffer: B) -> Buffer<S, B> {
         Buffer {
+            _slot: PhantomData,
             _pinned: PhantomPinned,
             shared: None,
             buffer,
@@ -37,25 +46,32 @@ impl<B: FromBytes + IntoBytes, const DRIVER_NUM: u32, const BUFFER_NUM: u32> Fro
 
 // Possible surprising semantics: A Buffer that is created but never allowed
 // will still clear its allow ID on drop!
-impl<B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM: u32> Drop
-    for Buffer<B, DRIVER_NUM, BUFFER_NUM>
-{
+impl<S: AllowNumber, B: FromBytes + IntoBytes + ?Sized> Drop for Buffer<S, B> {
     fn drop(&mut self) {
         if let Some(p) = self.shared {
-            unshare(DRIVER_NUM, BUFFER_NUM, p);
+            unshare(S::Driver::NUM, S::NUM, p, ReturnedBuffer::of(&self.buffer));
         }
     }
 }
 
-impl<B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM: u32>
-    Buffer<B, DRIVER_NUM, BUFFER_NUM>
-{
+impl<S: AllowNumber, B: FromBytes + IntoBytes + ?Sized> Buffer<S, B> {
+    /// Shares the buffer. If another buffer was already shared with this allow
+    /// ID, it is left shared and this fails with `ForeignBufferSwappedOut`.
     pub fn allow(self: Pin<&mut Self>, allow_type: DynamicType) -> Result<(), ErrorCode> {
         if self.shared.is_some() {
-            return Err(3);
+            return Err(ErrorCode::Already);
         }
         let this = unsafe { Pin::into_inner_unchecked(self) };
-        unsafe { allow_inner(DRIVER_NUM, BUFFER_NUM, &mut this.buffer, allow_type) }?;
+        let expected = ReturnedBuffer::EMPTY;
+        unsafe {
+            allow_inner(
+                S::Driver::NUM,
+                S::NUM,
+                as_slice(&mut this.buffer),
+                allow_type,
+                expected,
+            )
+        }?;
         this.shared = Some(allow_type);
         Ok(())
     }
@@ -77,64 +93,410 @@ impl<B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM:
         Some(&mut unsafe { Pin::into_inner_unchecked(self) }.buffer)
     }
 
+    /// Copies `dest.len()` bytes starting at `offset` into `dest`. Unlike
+    /// `buffer`, this works while the buffer is shared, unless it is shared
+    /// read-write. The copy may be torn by a concurrent kernel write.
+    pub fn read_volatile(self: Pin<&mut Self>, offset: usize, dest: &mut [u8]) -> Option<()> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(DynamicType::Rw) = this.shared {
+            return None;
+        }
+        assert!(
+            offset
+                .checked_add(dest.len())
+                .is_some_and(|end| end <= size_of_val(&this.buffer))
+        );
+        unsafe { read_volatile_bytes((&raw const this.buffer).cast::<u8>().add(offset), dest) };
+        Some(())
+    }
+
+    /// Views the buffer as an `H` followed by a payload. Fails with `Busy`
+    /// while the buffer is shared.
+    pub fn view<H: FromBytes + KnownLayout + Immutable>(
+        self: Pin<&Self>,
+    ) -> Result<View<'_, H>, ErrorCode>
+    where
+        B: Immutable,
+    {
+        view::parse(self.buffer().ok_or(ErrorCode::Busy)?.as_bytes())
+    }
+
+    /// Views the buffer as a mutable `H` followed by a payload. Fails with
+    /// `Busy` while the buffer is shared.
+    pub fn view_mut<H: FromBytes + IntoBytes + KnownLayout + Immutable>(
+        self: Pin<&mut Self>,
+    ) -> Result<ViewMut<'_, H>, ErrorCode> {
+        view::parse_mut(self.buffer_mut().ok_or(ErrorCode::Busy)?.as_mut_bytes())
+    }
+
+    /// Writes `header` to the start of the buffer, for example before sharing
+    /// it read-only, and returns the payload bytes after it. Fails with `Busy`
+    /// while the buffer is shared.
+    pub fn write_header<H: IntoBytes + KnownLayout + Immutable>(
+        self: Pin<&mut Self>,
+        header: H,
+    ) -> Result<&mut [u8], ErrorCode> {
+        view::write(
+            self.buffer_mut().ok_or(ErrorCode::Busy)?.as_mut_bytes(),
+            header,
+        )
+    }
+
+    /// Copies an `H` from the start of the buffer, retrying until the read is
+    /// not torn by a concurrent kernel write. Unlike `view`, this works while
+    /// the buffer is shared, unless it is shared read-write (`Busy`).
+    pub fn read_header<H: FromBytes + IntoBytes + Immutable>(
+        self: Pin<&mut Self>,
+    ) -> Result<H, ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(DynamicType::Rw) = this.shared {
+            return Err(ErrorCode::Busy);
+        }
+        unsafe { view::read_header((&raw const this.buffer).cast(), size_of_val(&this.buffer)) }
+    }
+
     pub fn unallow(self: Pin<&mut Self>) -> &mut B {
-        unsafe {
-            let this = Pin::into_inner_unchecked(self);
-            if let Some(p) = this.shared {
-                unshare(DRIVER_NUM, BUFFER_NUM, p);
-            }
-            this.shared = None;
-            &mut this.buffer
+        unsafe { Pin::into_inner_unchecked(self) }.unallow_unpinned()
+    }
+
+    // `unallow` for methods that have already unwrapped their `Pin`.
+    fn unallow_unpinned(&mut self) -> &mut B {
+        if let Some(p) = self.shared {
+            unshare(S::Driver::NUM, S::NUM, p, ReturnedBuffer::of(&self.buffer));
         }
+        self.shared = None;
+        &mut self.buffer
     }
 
     pub fn share_status(self: Pin<&Self>) -> Option<DynamicType> {
         self.shared
     }
 
+    /// Shares `other` in place of this buffer with a single Allow call, and
+    /// returns this buffer, which is unshared whether or not the call
+    /// succeeded. `other` is shared with this buffer's class, so if this
+    /// buffer is not shared, this fails with `Invalid`.
     pub fn replace_with<OB: FromBytes + IntoBytes + ?Sized>(
         self: Pin<&mut Self>,
-        other: Pin<&mut Buffer<OB, DRIVER_NUM, BUFFER_NUM>>,
+        other: Pin<&mut Buffer<S, OB>>,
     ) -> (&mut B, Result<(), ErrorCode>) {
         let this = unsafe { Pin::into_inner_unchecked(self) };
+        let (Some(allow_type), None) = (this.shared, other.shared) else {
+            let error = match other.shared {
+                Some(_) => ErrorCode::Already,
+                None => ErrorCode::Invalid,
+            };
+            return (this.unallow_unpinned(), Err(error));
+        };
         let other = unsafe { Pin::into_inner_unchecked(other) };
-        let result = if let Some(allow_type) = this.shared {
-            let _ = unsafe { allow_inner(DRIVER_NUM, BUFFER_NUM, &mut this.buffer, allow_type) };
-            this.shared = None;
-            other.shared = Some(allow_type);
-            Ok(())
-        } else {
-            Err(6)
+        let expected = ReturnedBuffer::of(&this.buffer);
+        let result = unsafe {
+            allow_inner(
+                S::Driver::NUM,
+                S::NUM,
+                as_slice(&mut other.buffer),
+                allow_type,
+                expected,
+            )
         };
+        match result {
+            Ok(()) => other.shared = Some(allow_type),
+            // The kernel swapped out a foreign buffer, which has been shared
+            // again in place of `other`, so this buffer was not shared.
+            Err(ErrorCode::ForeignBufferSwappedOut) => {}
+            // Either the call failed, so this buffer is still shared, or
+            // sharing a swapped-out foreign buffer again failed, and the
+            // unallow finds nothing shared.
+            Err(_) => unshare(S::Driver::NUM, S::NUM, allow_type, expected),
+        }
+        this.shared = None;
         (&mut this.buffer, result)
     }
 }
 
-unsafe fn allow_inner<B: FromBytes + IntoBytes + ?Sized>(
+impl<S: AllowNumber, B: FromBytes + IntoBytes + Immutable> Buffer<S, B> {
+    /// Reads the buffer, retrying until the read is not torn by a concurrent
+    /// kernel write. Works while the buffer is shared, unless it is shared
+    /// read-write. Returns `None` if it is, or if the kernel kept changing it.
+    pub fn read(self: Pin<&mut Self>) -> Option<B> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(DynamicType::Rw) = this.shared {
+            return None;
+        }
+        unsafe { read_untorn(&raw const this.buffer) }
+    }
+}
+
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> AllowBuffer<S> for Buffer<S, B> {
+    type Data = B;
+
+    fn allow(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        Buffer::allow(self, S::Class::CLASS)
+    }
+
+    fn unallow(self: Pin<&mut Self>) -> &mut B {
+        Buffer::unallow(self)
+    }
+
+    /// Swaps the buffers in a single Allow call. If this buffer is not shared,
+    /// `other` is simply allowed.
+    fn replace_with<'a>(
+        self: Pin<&'a mut Self>,
+        other: Pin<&mut Self>,
+    ) -> (&'a mut B, Result<(), ErrorCode>) {
+        if self.shared.is_none() {
+            let result = Buffer::allow(other, S::Class::CLASS);
+            return (Buffer::unallow(self), result);
+        }
+        Buffer::replace_with(self, other)
+    }
+}
+
+/// A Buffer that borrows a caller-owned slice instead of owning its data, so a
+/// library can share data it was handed without copying it into a `Buffer`.
+///
+/// Leaking a `BorrowedBuffer` would end the borrow while the kernel may still
+/// hold the slice, so it cannot be constructed directly: `with` and `with_mut`
+/// pin it for the duration of a closure and drop it (unallowing) afterwards.
+pub struct BorrowedBuffer<'a, S: AllowNumber> {
+    _slot: PhantomData<S>,
+    _pinned: PhantomPinned,
+    _borrow: PhantomData<&'a mut [u8]>,
+    shared: Option<DynamicType>,
+    writable: bool,
+    buffer: *mut [u8],
+}
+
+impl<S: AllowNumber> Drop for BorrowedBuffer<'_, S> {
+    fn drop(&mut self) {
+        if let Some(p) = self.shared {
+            unshare(
+                S::Driver::NUM,
+                S::NUM,
+                p,
+                ReturnedBuffer::of_slice(self.buffer),
+            );
+        }
+    }
+}
+
+impl<'a, S: AllowNumber> BorrowedBuffer<'a, S> {
+    fn scope<R>(buffer: *mut [u8], writable: bool, f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        f(pin!(BorrowedBuffer {
+            _slot: PhantomData,
+            _pinned: PhantomPinned,
+            _borrow: PhantomData,
+            shared: None,
+            writable,
+            buffer,
+        }))
+    }
+
+    /// Calls `f` with a pinned BorrowedBuffer for `buffer`, unallowing it when
+    /// `f` returns. The buffer can only be allowed read-only.
+    pub fn with<R>(buffer: &'a [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        Self::scope(ptr::from_ref(buffer).cast_mut(), false, f)
+    }
+
+    /// Calls `f` with a pinned BorrowedBuffer for `buffer`, unallowing it when
+    /// `f` returns.
+    pub fn with_mut<R>(buffer: &'a mut [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        Self::scope(buffer, true, f)
+    }
+
+    /// Shares the buffer. If another buffer was already shared with this allow
+    /// ID, it is left shared and this fails with `ForeignBufferSwappedOut`.
+    /// Fails with `Invalid` if the buffer was borrowed through `with` and
+    /// `allow_type` is not read-only.
+    pub fn allow(self: Pin<&mut Self>, allow_type: DynamicType) -> Result<(), ErrorCode> {
+        if self.shared.is_some() {
+            return Err(ErrorCode::Already);
+        }
+        if !self.writable && allow_type != DynamicType::Ro {
+            return Err(ErrorCode::Invalid);
+        }
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        unsafe {
+            allow_inner(
+                S::Driver::NUM,
+                S::NUM,
+                this.buffer,
+                allow_type,
+                ReturnedBuffer::EMPTY,
+            )
+        }?;
+        this.shared = Some(allow_type);
+        Ok(())
+    }
+
+    pub fn buffer(self: Pin<&Self>) -> Option<&[u8]> {
+        if self.shared.is_some() {
+            return None;
+        }
+        Some(unsafe { &*self.get_ref().buffer })
+    }
+
+    /// Returns the buffer if it is not shared and was borrowed through
+    /// `with_mut`.
+    pub fn buffer_mut(self: Pin<&mut Self>) -> Option<&mut [u8]> {
+        if self.shared.is_some() || !self.writable {
+            return None;
+        }
+        Some(unsafe { &mut *self.buffer })
+    }
+
+    pub fn unallow(self: Pin<&mut Self>) -> &[u8] {
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        if let Some(p) = this.shared {
+            unshare(
+                S::Driver::NUM,
+                S::NUM,
+                p,
+                ReturnedBuffer::of_slice(this.buffer),
+            );
+        }
+        this.shared = None;
+        unsafe { &*this.buffer }
+    }
+
+    pub fn share_status(self: Pin<&Self>) -> Option<DynamicType> {
+        self.shared
+    }
+}
+
+/// An upcall registration that tracks whether it is subscribed at runtime but
+/// which has a const ID.
+pub struct Subscription<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> {
+    _pinned: PhantomPinned,
+    subscribed: bool,
+    upcall: U,
+}
+
+impl<U: Default + Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> Default
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn default() -> Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM> {
+        Subscription {
+            _pinned: PhantomPinned,
+            subscribed: false,
+            upcall: Default::default(),
+        }
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> From<U>
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn from(upcall: U) -> Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM> {
+        Subscription {
+            _pinned: PhantomPinned,
+            subscribed: false,
+            upcall,
+        }
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> Drop
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn drop(&mut self) {
+        if self.subscribed {
+            unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+        }
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    pub fn subscribe(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        if self.subscribed {
+            return Err(ErrorCode::Already);
+        }
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        unsafe { subscribe_inner(DRIVER_NUM, SUBSCRIBE_NUM, &this.upcall) }?;
+        this.subscribed = true;
+        Ok(())
+    }
+
+    pub fn unsubscribe(self: Pin<&mut Self>) {
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        if this.subscribed {
+            unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+        }
+        this.subscribed = false;
+    }
+
+    pub fn is_subscribed(self: Pin<&Self>) -> bool {
+        self.subscribed
+    }
+
+    pub fn upcall(self: Pin<&Self>) -> &U {
+        &self.get_ref().upcall
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    AllowSubscription<DRIVER_NUM, SUBSCRIBE_NUM> for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    type Upcall = U;
+
+    fn subscribe(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        Subscription::subscribe(self)
+    }
+
+    fn unsubscribe(self: Pin<&mut Self>) {
+        Subscription::unsubscribe(self);
+    }
+
+    fn upcall(self: Pin<&Self>) -> &U {
+        Subscription::upcall(self)
+    }
+}
+
+/// The `dynamic_type` implementation, for code that is generic over
+/// `Implementation`.
+pub enum DynamicTypeImpl {}
+
+impl Implementation for DynamicTypeImpl {
+    type Buffer<S: AllowSlot, B: FromBytes + IntoBytes> = Buffer<S, B>;
+    type Subscription<U: Default + Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> =
+        Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>;
+}
+
+unsafe fn allow_inner(
     driver_num: u32,
     buffer_num: u32,
-    buffer: &mut B,
+    buffer: *mut [u8],
     allow_type: DynamicType,
+    expected: ReturnedBuffer,
 ) -> Result<(), ErrorCode> {
-    let (variant, r1, _, _) = unsafe {
+    let registers = unsafe {
         dynamic_allow(
             driver_num,
             buffer_num,
-            buffer as *mut B as *mut u8,
-            size_of_val(buffer),
+            buffer as *mut u8,
+            buffer.len(),
             allow_type,
         )
     };
-    if variant == 2 {
-        return Err(r1.addr() as u32);
-    }
-    Ok(())
+    let returned = decode_allow(registers).map_err(|(error, _)| error)?;
+    unsafe { check_swapped_out(driver_num, buffer_num, allow_type, returned, expected) }
 }
 
 /// No error handling is needed because if (driver_num, buffer_num) is not
-/// valid, then the buffer could not have been shared in the first place.
-fn unshare(driver_num: u32, buffer_num: u32, allow_type: DynamicType) {
-    unsafe {
-        dynamic_allow(driver_num, buffer_num, null_mut(), 0, allow_type);
+/// valid, then the buffer could not have been shared in the first place. If the
+/// kernel returns a buffer other than `expected`, this Buffer had already been
+/// swapped out, and `check_swapped_out` shares the foreign buffer again.
+fn unshare(driver_num: u32, buffer_num: u32, allow_type: DynamicType, expected: ReturnedBuffer) {
+    let registers = unsafe { dynamic_allow(driver_num, buffer_num, null_mut(), 0, allow_type) };
+    if let Ok(returned) = decode_allow(registers) {
+        let _ =
+            unsafe { check_swapped_out(driver_num, buffer_num, allow_type, returned, expected) };
     }
 }
+
+/// The bytes of `buffer`, as passed to Allow.
+fn as_slice<B: FromBytes + IntoBytes + ?Sized>(buffer: &mut B) -> *mut [u8] {
+    ptr::slice_from_raw_parts_mut(ptr::from_mut(buffer).cast(), size_of_val(buffer))
+}
@@ -0,0 +1,380 @@
+//! An in-process fake kernel that the system calls are routed to when the
+//! library is compiled for an architecture other than `arm`, `riscv32` or
+//! `riscv64`. This lets the Allow API implementations run under `cargo test` on
+//! the host.
+//!
+//! The fake kernel keeps a table of which buffer is shared with each allow ID
+//! and which upcall is registered with each subscribe ID, and a log of every
+//! system call that was made, so tests can verify both the final state and the
+//! exact order of the allow and unallow calls. All state is thread-local, so
+//! tests running in parallel do not interfere with each other.
+//!
+//! Nothing happens asynchronously: a test queues what the kernel should do
+//! during each Yield-Wait (or each wait within a Yield-WaitFor) with
+//! `on_yield`.
+
+use crate::{DynamicType, ErrorCode, UpcallFn};
+use core::cell::RefCell;
+use core::ptr::{null, null_mut, without_provenance_mut};
+use std::boxed::Box;
+use std::collections::{BTreeMap, VecDeque};
+use std::vec::Vec;
+
+/// A system call that was made to the fake kernel.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Syscall {
+    Allow {
+        allow_type: DynamicType,
+        driver_num: u32,
+        buffer_num: u32,
+        address: *mut u8,
+        len: usize,
+    },
+    Command {
+        driver_num: u32,
+        command_num: u32,
+        arg0: u32,
+        arg1: u32,
+    },
+    Subscribe {
+        driver_num: u32,
+        subscribe_num: u32,
+        upcall: *const (),
+        data: *const (),
+    },
+    YieldWait,
+    YieldWaitFor {
+        driver_num: u32,
+        subscribe_num: u32,
+    },
+}
+
+impl Syscall {
+    /// The Allow call that unshares the given allow ID.
+    pub fn unallow(allow_type: DynamicType, driver_num: u32, buffer_num: u32) -> Syscall {
+        Syscall::Allow {
+            allow_type,
+            driver_num,
+            buffer_num,
+            address: null_mut(),
+            len: 0,
+        }
+    }
+
+    /// The Subscribe call that unsubscribes the given subscribe ID.
+    pub fn unsubscribe(driver_num: u32, subscribe_num: u32) -> Syscall {
+        Syscall::Subscribe {
+            driver_num,
+            subscribe_num,
+            upcall: null(),
+            data: null(),
+        }
+    }
+
+    /// Returns true if this is an Allow call that shares no buffer (an
+    /// "unallow").
+    pub fn is_unallow(&self) -> bool {
+        matches!(self, Syscall::Allow { address, len: 0, .. } if address.is_null())
+    }
+
+    /// Returns true if this is a Subscribe call that registers the null upcall.
+    pub fn is_unsubscribe(&self) -> bool {
+        matches!(self, Syscall::Subscribe { upcall, .. } if upcall.is_null())
+    }
+}
+
+/// A buffer that is currently shared with the fake kernel.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shared {
+    pub address: *mut u8,
+    pub len: usize,
+}
+
+#[derive(Default)]
+struct Kernel {
+    // Keyed on (driver_num, buffer_num, allow_type) because RO and RW allow
+    // numbers are separate namespaces. Only non-empty buffers are stored.
+    allows: BTreeMap<(u32, u32, u32), (DynamicType, Shared)>,
+    // Keyed on (driver_num, subscribe_num). Null upcalls are not stored.
+    upcalls: BTreeMap<(u32, u32), (*const (), *const ())>,
+    log: Vec<Syscall>,
+    // `None` lets a call succeed, so a failure can be injected into a later
+    // call.
+    failures: VecDeque<Option<ErrorCode>>,
+    yields: VecDeque<Box<dyn FnOnce()>>,
+    // The subscribe ID a Yield-WaitFor is blocked on, and the arguments of its
+    // upcall once it has been scheduled.
+    wait_for: Option<(u32, u32)>,
+    waited: Option<[u32; 3]>,
+}
+
+std::thread_local! {
+    static KERNEL: RefCell<Kernel> = RefCell::default();
+}
+
+/// Clears the allow and subscribe tables, the system call log, and any pending
+/// failures and `on_yield` actions (and the `no_dynamic` ownership registry and
+/// the trace, if enabled).
+pub fn reset() {
+    KERNEL.with_borrow_mut(|kernel| *kernel = Kernel::default());
+    #[cfg(feature = "registry")]
+    crate::registry::reset();
+    #[cfg(feature = "trace")]
+    crate::trace::clear();
+}
+
+/// Returns the system calls made since the last `reset` or `take_log`, in the
+/// order they were made, and clears the log.
+pub fn take_log() -> Vec<Syscall> {
+    KERNEL.with_borrow_mut(|kernel| core::mem::take(&mut kernel.log))
+}
+
+/// Returns the buffer that is currently shared with the given allow ID, if any.
+pub fn shared(allow_type: DynamicType, driver_num: u32, buffer_num: u32) -> Option<Shared> {
+    KERNEL.with_borrow(|kernel| {
+        kernel
+            .allows
+            .get(&(driver_num, buffer_num, allow_type as u32))
+            .map(|&(_, shared)| shared)
+    })
+}
+
+/// Returns every buffer that is currently shared, as (driver_num, buffer_num,
+/// allow_type, buffer) tuples.
+pub fn allow_table() -> Vec<(u32, u32, DynamicType, Shared)> {
+    KERNEL.with_borrow(|kernel| {
+        kernel
+            .allows
+            .iter()
+            .map(|(&(driver_num, buffer_num, _), &(allow_type, shared))| {
+                (driver_num, buffer_num, allow_type, shared)
+            })
+            .collect()
+    })
+}
+
+/// Writes `data` at `offset` into the buffer shared with the given allow ID, as
+/// a capsule would. Returns false if no buffer is shared with that ID. Panics
+/// if the allow ID is read-only or `data` does not fit in the shared buffer.
+pub fn write(
+    allow_type: DynamicType,
+    driver_num: u32,
+    buffer_num: u32,
+    offset: usize,
+    data: &[u8],
+) -> bool {
+    assert!(
+        allow_type != DynamicType::Ro,
+        "the kernel cannot write RO buffers"
+    );
+    let Some(shared) = shared(allow_type, driver_num, buffer_num) else {
+        return false;
+    };
+    assert!(
+        offset
+            .checked_add(data.len())
+            .is_some_and(|end| end <= shared.len)
+    );
+    unsafe {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), shared.address.add(offset), data.len())
+    };
+    true
+}
+
+/// The byte `scribble` fills buffers with.
+pub const SCRIBBLE: u8 = 0xA5;
+
+/// Fills every buffer that is shared read-write or userspace-readable with
+/// `SCRIBBLE`, as a capsule may do at any time while it holds a buffer. Tests
+/// call this between operations to check that the app never holds a reference
+/// to memory the kernel can still write. Returns the number of buffers written.
+pub fn scribble() -> usize {
+    let writable: Vec<Shared> = KERNEL.with_borrow(|kernel| {
+        kernel
+            .allows
+            .values()
+            .filter(|&&(allow_type, _)| allow_type != DynamicType::Ro)
+            .map(|&(_, shared)| shared)
+            .collect()
+    });
+    for shared in &writable {
+        unsafe { core::ptr::write_bytes(shared.address, SCRIBBLE, shared.len) };
+    }
+    writable.len()
+}
+
+/// Returns true if a (non-null) upcall is registered with the given subscribe
+/// ID.
+pub fn subscribed(driver_num: u32, subscribe_num: u32) -> bool {
+    KERNEL.with_borrow(|kernel| kernel.upcalls.contains_key(&(driver_num, subscribe_num)))
+}
+
+/// Invokes the upcall registered with the given subscribe ID, as the kernel
+/// would during a Yield. If a Yield-WaitFor is blocked on that subscribe ID,
+/// the arguments are returned from it instead, whether or not an upcall is
+/// registered. Returns false if the upcall was not delivered.
+pub fn upcall(driver_num: u32, subscribe_num: u32, args: [u32; 3]) -> bool {
+    let waited = KERNEL.with_borrow_mut(|kernel| {
+        if kernel.wait_for != Some((driver_num, subscribe_num)) {
+            return false;
+        }
+        kernel.wait_for = None;
+        kernel.waited = Some(args);
+        true
+    });
+    if waited {
+        return true;
+    }
+    // Copy the upcall out so the kernel state is not borrowed while it runs.
+    let Some((upcall, data)) =
+        KERNEL.with_borrow(|kernel| kernel.upcalls.get(&(driver_num, subscribe_num)).copied())
+    else {
+        return false;
+    };
+    // SAFETY: Only `kernel_upcall` function pointers are passed to Subscribe,
+    // and the `Subscription` that registered it (which `data` points into) is
+    // pinned and unsubscribes before it is dropped.
+    unsafe {
+        let upcall: UpcallFn = core::mem::transmute(upcall);
+        upcall(args[0], args[1], args[2], data);
+    }
+    true
+}
+
+/// Makes the next system call fail with the given error code. Calls to this
+/// function queue up, so several consecutive failures can be injected. A failed
+/// call is still logged, but does not modify the allow table.
+pub fn fail_next(error_code: ErrorCode) {
+    KERNEL.with_borrow_mut(|kernel| kernel.failures.push_back(Some(error_code)));
+}
+
+/// Lets the next system call run normally. Queued before `fail_next`, this
+/// injects a failure into a later call, such as the Command of a sequence.
+/// Yield-Wait and Yield-WaitFor do not take part in the queue, as they cannot
+/// fail.
+pub fn pass_next() {
+    KERNEL.with_borrow_mut(|kernel| kernel.failures.push_back(None));
+}
+
+/// Queues `action` to run during a Yield-Wait, typically writing into a shared
+/// buffer and then calling `upcall`. Each Yield-Wait runs the next queued
+/// action, and a Yield-WaitFor runs them until its upcall is scheduled.
+pub fn on_yield(action: impl FnOnce() + 'static) {
+    KERNEL.with_borrow_mut(|kernel| kernel.yields.push_back(Box::new(action)));
+}
+
+/// Fake Yield-Wait system call. Runs the next `on_yield` action, and panics if
+/// there is none, as a real Yield-Wait would never return.
+pub(crate) fn yield_wait() {
+    let action = KERNEL.with_borrow_mut(|kernel| {
+        kernel.log.push(Syscall::YieldWait);
+        kernel.yields.pop_front()
+    });
+    // The action is run with the kernel state unborrowed, so it can make fake
+    // kernel calls.
+    action.expect("Yield-Wait with no on_yield action queued would block forever")();
+}
+
+/// Fake Yield-WaitFor system call. Runs `on_yield` actions until one schedules
+/// the upcall with the given subscribe ID, and panics if they run out first,
+/// as a real Yield-WaitFor would never return.
+pub(crate) fn yield_wait_for(driver_num: u32, subscribe_num: u32) -> [u32; 3] {
+    KERNEL.with_borrow_mut(|kernel| {
+        kernel.log.push(Syscall::YieldWaitFor {
+            driver_num,
+            subscribe_num,
+        });
+        kernel.wait_for = Some((driver_num, subscribe_num));
+    });
+    loop {
+        let action = KERNEL.with_borrow_mut(|kernel| kernel.yields.pop_front());
+        action.expect("Yield-WaitFor with no on_yield action queued would block forever")();
+        if let Some(args) = KERNEL.with_borrow_mut(|kernel| kernel.waited.take()) {
+            return args;
+        }
+    }
+}
+
+/// Fake Allow system call. Returns the same registers as the real system call:
+/// Failure with 2 u32 (the error code and the passed buffer) or Success with 2
+/// u32 (the previously-shared buffer).
+pub(crate) fn allow(
+    driver_num: u32,
+    buffer_num: u32,
+    address: *mut u8,
+    len: usize,
+    allow_type: DynamicType,
+) -> (u32, *mut u8, *mut u8, usize) {
+    KERNEL.with_borrow_mut(|kernel| {
+        kernel.log.push(Syscall::Allow {
+            allow_type,
+            driver_num,
+            buffer_num,
+            address,
+            len,
+        });
+        if let Some(error_code) = kernel.failures.pop_front().flatten() {
+            return (2, without_provenance_mut(error_code as usize), address, len);
+        }
+        let key = (driver_num, buffer_num, allow_type as u32);
+        let previous = match len {
+            0 => kernel.allows.remove(&key),
+            _ => kernel
+                .allows
+                .insert(key, (allow_type, Shared { address, len })),
+        };
+        match previous {
+            None => (130, null_mut(), null_mut(), 0),
+            Some((_, previous)) => (
+                130,
+                previous.address,
+                without_provenance_mut(previous.len),
+                0,
+            ),
+        }
+    })
+}
+
+/// Fake Subscribe system call. Returns r0 and r1: Success with 2 u32 or Failure
+/// with 2 u32 (r2 and r3 are not modelled).
+pub(crate) fn subscribe(
+    driver_num: u32,
+    subscribe_num: u32,
+    upcall: *const (),
+    data: *const (),
+) -> (u32, u32) {
+    KERNEL.with_borrow_mut(|kernel| {
+        kernel.log.push(Syscall::Subscribe {
+            driver_num,
+            subscribe_num,
+            upcall,
+            data,
+        });
+        if let Some(error_code) = kernel.failures.pop_front().flatten() {
+            return (2, error_code as u32);
+        }
+        let key = (driver_num, subscribe_num);
+        match upcall.is_null() {
+            true => kernel.upcalls.remove(&key),
+            false => kernel.upcalls.insert(key, (upcall, data)),
+        };
+        (130, 0)
+    })
+}
+
+/// Fake Command system call. Returns r0 and r1: Success, or Failure with the
+/// injected error code.
+pub(crate) fn command(driver_num: u32, command_num: u32, arg0: u32, arg1: u32) -> [u32; 2] {
+    KERNEL.with_borrow_mut(|kernel| {
+        kernel.log.push(Syscall::Command {
+            driver_num,
+            command_num,
+            arg0,
+            arg1,
+        });
+        match kernel.failures.pop_front().flatten() {
+            None => [128, 0],
+            Some(error_code) => [0, error_code as u32],
+        }
+    })
+}
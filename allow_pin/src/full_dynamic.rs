@@ -1,24 +1,114 @@
 //! An Allow buffer that tracks whether the buffer is allowed, the allow type,
 //! and the allow ID at runtime.
 
+use crate::view::{self, View, ViewMut};
 use crate::*;
 use core::mem::size_of_val;
+#[cfg(not(feature = "unpacked_share_info"))]
+use core::num::NonZeroU32;
+use core::ops::Range;
+use core::pin::pin;
+use core::ptr;
+use zerocopy::KnownLayout;
 
-pub use crate::DynamicType;
-pub type ErrorCode = u32;
+pub use crate::{
+    AllowRo, AllowRw, AllowSlot, AllowUserspaceReadable, Driver, DynamicType, ErrorCode,
+};
 
 pub struct Buffer<B: FromBytes + IntoBytes + ?Sized> {
     _pinned: PhantomPinned,
-    shared: Option<ShareInfo>,
+    shared: Option<AllowId>,
     buffer: B,
 }
 
-struct ShareInfo {
+/// The allow type and ID a buffer is shared with, packed into one word: the
+/// driver number in bits 0-19, the allow number in bits 20-27, the class in
+/// bits 28-29, and bit 31, which is always set so that `Option<AllowId>` is one
+/// word too. Tock's driver numbers fit in 20 bits and grants declare their
+/// allow counts as `u8`, so every allow ID a kernel accepts can be packed.
+#[cfg(not(feature = "unpacked_share_info"))]
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct AllowId(NonZeroU32);
+
+#[cfg(not(feature = "unpacked_share_info"))]
+impl AllowId {
+    const DRIVER_BITS: u32 = 20;
+    const BUFFER_BITS: u32 = 8;
+    const CLASS_SHIFT: u32 = Self::DRIVER_BITS + Self::BUFFER_BITS;
+    const SHARED: NonZeroU32 = NonZeroU32::new(1 << 31).unwrap();
+
+    /// Fails with `NoDevice` if the driver number, or `Invalid` if the allow
+    /// number, is out of range, as the kernel would for an ID no driver has.
+    fn new(allow_type: DynamicType, driver_num: u32, buffer_num: u32) -> Result<Self, ErrorCode> {
+        if driver_num >> Self::DRIVER_BITS != 0 {
+            return Err(ErrorCode::NoDevice);
+        }
+        if buffer_num >> Self::BUFFER_BITS != 0 {
+            return Err(ErrorCode::Invalid);
+        }
+        let class = match allow_type {
+            DynamicType::Rw => 0,
+            DynamicType::Ro => 1,
+            DynamicType::UserspaceReadable => 2,
+        };
+        Ok(AllowId(
+            Self::SHARED
+                | class << Self::CLASS_SHIFT
+                | buffer_num << Self::DRIVER_BITS
+                | driver_num,
+        ))
+    }
+
+    fn allow_type(self) -> DynamicType {
+        match self.0.get() >> Self::CLASS_SHIFT & 0b11 {
+            0 => DynamicType::Rw,
+            1 => DynamicType::Ro,
+            _ => DynamicType::UserspaceReadable,
+        }
+    }
+
+    fn driver_num(self) -> u32 {
+        self.0.get() & ((1 << Self::DRIVER_BITS) - 1)
+    }
+
+    fn buffer_num(self) -> u32 {
+        self.0.get() >> Self::DRIVER_BITS & ((1 << Self::BUFFER_BITS) - 1)
+    }
+}
+
+/// The allow type and ID a buffer is shared with, as separate fields: the
+/// layout before packing, kept for the size comparison against it.
+#[cfg(feature = "unpacked_share_info")]
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct AllowId {
     allow_type: DynamicType,
     driver_num: u32,
     buffer_num: u32,
 }
 
+#[cfg(feature = "unpacked_share_info")]
+impl AllowId {
+    fn new(allow_type: DynamicType, driver_num: u32, buffer_num: u32) -> Result<Self, ErrorCode> {
+        Ok(AllowId {
+            allow_type,
+            driver_num,
+            buffer_num,
+        })
+    }
+
+    fn allow_type(self) -> DynamicType {
+        self.allow_type
+    }
+
+    fn driver_num(self) -> u32 {
+        self.driver_num
+    }
+
+    fn buffer_num(self) -> u32 {
+        self.buffer_num
+    }
+}
+
 impl<B: Default + FromBytes + IntoBytes> Default for Buffer<B> {
     fn default() -> Buffer<B> {
         Buffer {
@@ -41,43 +131,82 @@ impl<B: FromBytes + IntoBytes> From<B> for Buffer<B> {
 
 impl<B: FromBytes + IntoBytes + ?Sized> Drop for Buffer<B> {
     fn drop(&mut self) {
-        unshare_if_shared(&mut self.shared);
+        let buffer = self.as_slice();
+        unshare_if_shared(&mut self.shared, buffer);
     }
 }
 
 impl<B: FromBytes + IntoBytes + ?Sized> Buffer<B> {
+    /// Shares the buffer. If another buffer was already shared with this allow
+    /// ID, it is left shared and this fails with `ForeignBufferSwappedOut`.
+    pub fn allow(
+        self: Pin<&mut Self>,
+        allow_type: DynamicType,
+        driver_num: u32,
+        buffer_num: u32,
+    ) -> Result<(), ErrorCode> {
+        let len = size_of_val(&self.buffer);
+        self.allow_range(0..len, allow_type, driver_num, buffer_num)
+    }
+
     // TODO: Try pulling the interior out into its own function that is not
     // generic.
-    pub fn allow(
+    /// Shares only the bytes of the buffer in `range`, for example the payload
+    /// after a header the app keeps to itself. The whole buffer is unavailable
+    /// until it is unallowed, which returns all of it. Fails with `Size` if
+    /// `range` is not within the buffer.
+    pub fn allow_range(
         self: Pin<&mut Self>,
+        range: Range<usize>,
         allow_type: DynamicType,
         driver_num: u32,
         buffer_num: u32,
     ) -> Result<(), ErrorCode> {
         if self.shared.is_some() {
-            return Err(3);
+            return Err(ErrorCode::Already);
         }
+        if range.start > range.end || range.end > size_of_val(&self.buffer) {
+            return Err(ErrorCode::Size);
+        }
+        let id = AllowId::new(allow_type, driver_num, buffer_num)?;
         let this = unsafe { Pin::into_inner_unchecked(self) };
-        let (variant, r1, _, _) = unsafe {
+        let registers = unsafe {
             dynamic_allow(
                 driver_num,
                 buffer_num,
-                &mut this.buffer as *mut _ as *mut u8,
-                size_of_val(&this.buffer),
+                (&raw mut this.buffer).cast::<u8>().add(range.start),
+                range.len(),
                 allow_type,
             )
         };
-        if variant == 2 {
-            return Err(r1.addr() as u32);
-        }
-        this.shared = Some(ShareInfo {
-            allow_type,
-            driver_num,
-            buffer_num,
-        });
+        let returned = decode_allow(registers).map_err(|(error, _)| error)?;
+        unsafe {
+            check_swapped_out(
+                driver_num,
+                buffer_num,
+                allow_type,
+                returned,
+                ReturnedBuffer::EMPTY,
+            )
+        }?;
+        this.shared = Some(id);
         Ok(())
     }
 
+    /// Shares the buffer with a typed allow slot, which determines the allow
+    /// type and ID.
+    pub fn allow_slot<S: AllowSlot>(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        self.allow(S::Class::CLASS, S::Driver::NUM, S::NUM)
+    }
+
+    /// Shares the bytes in `range` with a typed allow slot, as `allow_range`.
+    pub fn allow_slot_range<S: AllowSlot>(
+        self: Pin<&mut Self>,
+        range: Range<usize>,
+    ) -> Result<(), ErrorCode> {
+        self.allow_range(range, S::Class::CLASS, S::Driver::NUM, S::NUM)
+    }
+
     pub fn buffer(self: Pin<&Self>) -> Option<&B> {
         if self.shared.is_some() {
             return None;
@@ -92,22 +221,205 @@ impl<B: FromBytes + IntoBytes + ?Sized> Buffer<B> {
         Some(&mut unsafe { Pin::into_inner_unchecked(self) }.buffer)
     }
 
+    /// Copies `dest.len()` bytes starting at `offset` into `dest`. Unlike
+    /// `buffer`, this works while the buffer is shared, unless it is shared
+    /// read-write. The copy may be torn by a concurrent kernel write.
+    pub fn read_volatile(self: Pin<&mut Self>, offset: usize, dest: &mut [u8]) -> Option<()> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.shared_rw() {
+            return None;
+        }
+        assert!(
+            offset
+                .checked_add(dest.len())
+                .is_some_and(|end| end <= size_of_val(&this.buffer))
+        );
+        unsafe { read_volatile_bytes((&raw const this.buffer).cast::<u8>().add(offset), dest) };
+        Some(())
+    }
+
+    /// Views the buffer as an `H` followed by a payload. Fails with `Busy`
+    /// while the buffer is shared.
+    pub fn view<H: FromBytes + KnownLayout + Immutable>(
+        self: Pin<&Self>,
+    ) -> Result<View<'_, H>, ErrorCode>
+    where
+        B: Immutable,
+    {
+        view::parse(self.buffer().ok_or(ErrorCode::Busy)?.as_bytes())
+    }
+
+    /// Views the buffer as a mutable `H` followed by a payload. Fails with
+    /// `Busy` while the buffer is shared.
+    pub fn view_mut<H: FromBytes + IntoBytes + KnownLayout + Immutable>(
+        self: Pin<&mut Self>,
+    ) -> Result<ViewMut<'_, H>, ErrorCode> {
+        view::parse_mut(self.buffer_mut().ok_or(ErrorCode::Busy)?.as_mut_bytes())
+    }
+
+    /// Writes `header` to the start of the buffer, for example before sharing
+    /// it read-only, and returns the payload bytes after it. Fails with `Busy`
+    /// while the buffer is shared.
+    pub fn write_header<H: IntoBytes + KnownLayout + Immutable>(
+        self: Pin<&mut Self>,
+        header: H,
+    ) -> Result<&mut [u8], ErrorCode> {
+        view::write(
+            self.buffer_mut().ok_or(ErrorCode::Busy)?.as_mut_bytes(),
+            header,
+        )
+    }
+
+    /// Copies an `H` from the start of the buffer, retrying until the read is
+    /// not torn by a concurrent kernel write. Unlike `view`, this works while
+    /// the buffer is shared, unless it is shared read-write (`Busy`).
+    pub fn read_header<H: FromBytes + IntoBytes + Immutable>(
+        self: Pin<&mut Self>,
+    ) -> Result<H, ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.shared_rw() {
+            return Err(ErrorCode::Busy);
+        }
+        unsafe { view::read_header((&raw const this.buffer).cast(), size_of_val(&this.buffer)) }
+    }
+
+    /// Unshares the buffer (or the range of it that is shared) and returns
+    /// the whole buffer.
     pub fn unallow(self: Pin<&mut Self>) -> &mut B {
         let this = unsafe { Pin::into_inner_unchecked(self) };
-        unshare_if_shared(&mut this.shared);
+        let buffer = this.as_slice();
+        unshare_if_shared(&mut this.shared, buffer);
         &mut this.buffer
     }
+
+    fn shared_rw(&self) -> bool {
+        self.shared
+            .is_some_and(|id| id.allow_type() == DynamicType::Rw)
+    }
+
+    /// The whole buffer as raw bytes, whichever range of it is shared.
+    fn as_slice(&self) -> *const [u8] {
+        ptr::slice_from_raw_parts((&raw const self.buffer).cast(), size_of_val(&self.buffer))
+    }
+}
+
+impl<B: FromBytes + IntoBytes + Immutable> Buffer<B> {
+    /// Reads the buffer, retrying until the read is not torn by a concurrent
+    /// kernel write. Works while the buffer is shared, unless it is shared
+    /// read-write. Returns `None` if it is, or if the kernel kept changing it.
+    pub fn read(self: Pin<&mut Self>) -> Option<B> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.shared_rw() {
+            return None;
+        }
+        unsafe { read_untorn(&raw const this.buffer) }
+    }
 }
 
-pub struct StaticBuffer<B: FromBytes + IntoBytes + 'static> {
+/// Shares the buffer with `S` through `allow_slot`.
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> AllowBuffer<S> for Buffer<B> {
+    type Data = B;
+
+    fn allow(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        self.allow_slot::<S>()
+    }
+
+    fn unallow(self: Pin<&mut Self>) -> &mut B {
+        Buffer::unallow(self)
+    }
+
+    /// Swaps the buffers in a single Allow call if this buffer is shared with
+    /// `S` and `other` is not shared. Otherwise this unallows and then allows,
+    /// as the default does.
+    fn replace_with<'a>(
+        self: Pin<&'a mut Self>,
+        other: Pin<&mut Self>,
+    ) -> (&'a mut B, Result<(), ErrorCode>) {
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        let other = unsafe { Pin::into_inner_unchecked(other) };
+        let id = AllowId::new(S::Class::CLASS, S::Driver::NUM, S::NUM).ok();
+        if this.shared.is_none() || this.shared != id || other.shared.is_some() {
+            let buffer = Buffer::unallow(unsafe { Pin::new_unchecked(this) });
+            let result = unsafe { Pin::new_unchecked(other) }.allow_slot::<S>();
+            return (buffer, result);
+        }
+        let buffer = this.as_slice();
+        let registers = unsafe {
+            dynamic_allow(
+                S::Driver::NUM,
+                S::NUM,
+                (&raw mut other.buffer).cast(),
+                size_of_val(&other.buffer),
+                S::Class::CLASS,
+            )
+        };
+        let result = match decode_allow(registers) {
+            // The call failed, so this buffer is still shared.
+            Err((error, _)) => {
+                unshare_if_shared(&mut this.shared, buffer);
+                Err(error)
+            }
+            Ok(returned) if holds(buffer, returned) => Ok(()),
+            // This buffer had been swapped out: a foreign buffer is shared
+            // again in place of `other`, or sharing it failed and nothing is.
+            Ok(returned) => unsafe {
+                check_swapped_out(
+                    S::Driver::NUM,
+                    S::NUM,
+                    S::Class::CLASS,
+                    returned,
+                    ReturnedBuffer::EMPTY,
+                )
+            },
+        };
+        if result.is_ok() {
+            other.shared = id;
+        }
+        this.shared = None;
+        (&mut this.buffer, result)
+    }
+}
+
+/// A `'static` buffer, borrowed either read-only or read-write. The kind of
+/// reference determines the kind of allow.
+pub enum StaticRef<B: FromBytes + IntoBytes + ?Sized + 'static> {
+    Ro(&'static B),
+    Rw(&'static mut B),
+}
+
+impl<B: FromBytes + IntoBytes + ?Sized> StaticRef<B> {
+    fn get(&self) -> &B {
+        match self {
+            StaticRef::Ro(buffer) => buffer,
+            StaticRef::Rw(buffer) => buffer,
+        }
+    }
+}
+
+/// An Allow buffer for data that lives in a `static`, so large buffers do not
+/// need to be on the stack. Shares `&'static B` read-only and
+/// `&'static mut B` read-write.
+pub struct StaticBuffer<B: FromBytes + IntoBytes + ?Sized + 'static> {
     _pinned: PhantomPinned,
-    buffer_ref: Option<&'static B>,
-    // Reuse the `ShareInfo` struct to save memory
-    shared: Option<ShareInfo>,
+    buffer_ref: Option<StaticRef<B>>,
+    // Reuse the `AllowId` struct to save memory
+    shared: Option<AllowId>,
 }
 
-impl<B: FromBytes + IntoBytes + 'static> From<&'static B> for StaticBuffer<B> {
+impl<B: FromBytes + IntoBytes + ?Sized + 'static> From<&'static B> for StaticBuffer<B> {
     fn from(buffer: &'static B) -> Self {
+        Self::from(StaticRef::Ro(buffer))
+    }
+}
+
+impl<B: FromBytes + IntoBytes + ?Sized + 'static> From<&'static mut B> for StaticBuffer<B> {
+    fn from(buffer: &'static mut B) -> Self {
+        Self::from(StaticRef::Rw(buffer))
+    }
+}
+
+impl<B: FromBytes + IntoBytes + ?Sized + 'static> From<StaticRef<B>> for StaticBuffer<B> {
+    fn from(buffer: StaticRef<B>) -> Self {
         Self {
             _pinned: Default::default(),
             buffer_ref: Some(buffer),
@@ -116,7 +428,7 @@ impl<B: FromBytes + IntoBytes + 'static> From<&'static B> for StaticBuffer<B> {
     }
 }
 
-impl<B: FromBytes + IntoBytes> Default for StaticBuffer<B> {
+impl<B: FromBytes + IntoBytes + ?Sized> Default for StaticBuffer<B> {
     fn default() -> Self {
         Self {
             _pinned: Default::default(),
@@ -126,56 +438,311 @@ impl<B: FromBytes + IntoBytes> Default for StaticBuffer<B> {
     }
 }
 
-impl<B: FromBytes + IntoBytes> Drop for StaticBuffer<B> {
+impl<B: FromBytes + IntoBytes + ?Sized> Drop for StaticBuffer<B> {
     fn drop(&mut self) {
-        unshare_if_shared(&mut self.shared);
+        let buffer = self.as_slice();
+        unshare_if_shared(&mut self.shared, buffer);
     }
 }
 
-impl<B: FromBytes + IntoBytes> StaticBuffer<B> {
+impl<B: FromBytes + IntoBytes + ?Sized> StaticBuffer<B> {
+    /// Shares the buffer: read-only if it was created from a `&'static B`, and
+    /// read-write if it was created from a `&'static mut B`.
     pub fn allow(self: Pin<&mut Self>, driver_num: u32, buffer_num: u32) -> Result<(), ErrorCode> {
         if self.shared.is_some() {
-            return Err(3);
+            return Err(ErrorCode::Already);
+        }
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        let (address, len, allow_type) = match &mut this.buffer_ref {
+            None => return Err(ErrorCode::NoMem),
+            Some(StaticRef::Ro(buffer)) => (
+                *buffer as *const B as *mut u8,
+                size_of_val(*buffer),
+                DynamicType::Ro,
+            ),
+            Some(StaticRef::Rw(buffer)) => (
+                *buffer as *mut B as *mut u8,
+                size_of_val(*buffer),
+                DynamicType::Rw,
+            ),
+        };
+        let id = AllowId::new(allow_type, driver_num, buffer_num)?;
+        let registers = unsafe { dynamic_allow(driver_num, buffer_num, address, len, allow_type) };
+        let returned = decode_allow(registers).map_err(|(error, _)| error)?;
+        unsafe {
+            check_swapped_out(
+                driver_num,
+                buffer_num,
+                allow_type,
+                returned,
+                ReturnedBuffer::EMPTY,
+            )
+        }?;
+        this.shared = Some(id);
+        Ok(())
+    }
+
+    pub fn buffer(self: Pin<&Self>) -> Option<&B> {
+        if self.shared.is_some() {
+            return None;
+        }
+        self.get_ref().buffer_ref.as_ref().map(StaticRef::get)
+    }
+
+    /// Returns the buffer if it is not shared and was created from a
+    /// `&'static mut B`.
+    pub fn buffer_mut(self: Pin<&mut Self>) -> Option<&mut B> {
+        if self.shared.is_some() {
+            return None;
         }
-        if self.buffer_ref.is_none() {
-            return Err(9);
+        match &mut unsafe { Pin::into_inner_unchecked(self) }.buffer_ref {
+            Some(StaticRef::Rw(buffer)) => Some(buffer),
+            _ => None,
         }
+    }
 
+    pub fn unallow(self: Pin<&mut Self>) -> Option<&B> {
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        let buffer = this.as_slice();
+        unshare_if_shared(&mut this.shared, buffer);
+        this.buffer_ref.as_ref().map(StaticRef::get)
+    }
+
+    /// Unallows the buffer and gives back the `'static` reference it was
+    /// created from, leaving this `StaticBuffer` empty.
+    pub fn take(self: Pin<&mut Self>) -> Option<StaticRef<B>> {
         let this = unsafe { Pin::into_inner_unchecked(self) };
+        let buffer = this.as_slice();
+        unshare_if_shared(&mut this.shared, buffer);
+        this.buffer_ref.take()
+    }
+
+    /// The buffer as raw bytes, which are null if there is none.
+    fn as_slice(&self) -> *const [u8] {
+        self.buffer_ref
+            .as_ref()
+            .map_or(ptr::slice_from_raw_parts(ptr::null(), 0), |buffer| {
+                ptr::slice_from_raw_parts(
+                    ptr::from_ref(buffer.get()).cast(),
+                    size_of_val(buffer.get()),
+                )
+            })
+    }
+}
+
+/// A Buffer that borrows a caller-owned slice instead of owning its data, so a
+/// library can share data it was handed without copying it into a `Buffer`. A
+/// slice borrowed through `with` can only be allowed read-only.
+///
+/// Leaking a `BorrowedBuffer` would end the borrow while the kernel may still
+/// hold the slice, so it cannot be constructed directly: `with` and `with_mut`
+/// pin it for the duration of a closure and drop it (unallowing) afterwards.
+pub struct BorrowedBuffer<'a> {
+    _pinned: PhantomPinned,
+    _borrow: PhantomData<&'a mut [u8]>,
+    shared: Option<AllowId>,
+    writable: bool,
+    buffer: *mut [u8],
+}
+
+impl Drop for BorrowedBuffer<'_> {
+    fn drop(&mut self) {
+        unshare_if_shared(&mut self.shared, self.buffer);
+    }
+}
+
+impl<'a> BorrowedBuffer<'a> {
+    fn scope<R>(buffer: *mut [u8], writable: bool, f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        f(pin!(BorrowedBuffer {
+            _pinned: PhantomPinned,
+            _borrow: PhantomData,
+            shared: None,
+            writable,
+            buffer,
+        }))
+    }
+
+    /// Calls `f` with a pinned BorrowedBuffer for `buffer`, unallowing it when
+    /// `f` returns.
+    pub fn with<R>(buffer: &'a [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        Self::scope(ptr::from_ref(buffer).cast_mut(), false, f)
+    }
 
-        // SAFETY: `buffer_ref` previously checked to be `Some`
-        let (variant, r1, _, _) = unsafe {
+    /// Calls `f` with a pinned BorrowedBuffer for `buffer`, unallowing it when
+    /// `f` returns.
+    pub fn with_mut<R>(buffer: &'a mut [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        Self::scope(buffer, true, f)
+    }
+
+    /// Shares the buffer. Fails with `Invalid` if a slice borrowed through
+    /// `with` is allowed as anything other than `DynamicType::Ro`.
+    pub fn allow(
+        self: Pin<&mut Self>,
+        allow_type: DynamicType,
+        driver_num: u32,
+        buffer_num: u32,
+    ) -> Result<(), ErrorCode> {
+        if self.shared.is_some() {
+            return Err(ErrorCode::Already);
+        }
+        if !self.writable && allow_type != DynamicType::Ro {
+            return Err(ErrorCode::Invalid);
+        }
+        let id = AllowId::new(allow_type, driver_num, buffer_num)?;
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        let registers = unsafe {
             dynamic_allow(
                 driver_num,
                 buffer_num,
-                &mut this.buffer_ref.unwrap_unchecked() as *mut _ as *mut u8,
-                size_of_val(this.buffer_ref.unwrap_unchecked()),
-                DynamicType::Ro,
+                this.buffer as *mut u8,
+                this.buffer.len(),
+                allow_type,
             )
         };
-        if variant == 2 {
-            return Err(r1.addr() as u32);
-        }
-        this.shared = Some(ShareInfo {
-            allow_type: DynamicType::Ro,
-            driver_num,
-            buffer_num,
-        });
+        let returned = decode_allow(registers).map_err(|(error, _)| error)?;
+        unsafe {
+            check_swapped_out(
+                driver_num,
+                buffer_num,
+                allow_type,
+                returned,
+                ReturnedBuffer::EMPTY,
+            )
+        }?;
+        this.shared = Some(id);
         Ok(())
     }
 
-    pub fn buffer(self: Pin<&Self>) -> Option<&B> {
+    /// Shares the buffer with a typed allow slot, which determines the allow
+    /// type and ID.
+    pub fn allow_slot<S: AllowSlot>(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        self.allow(S::Class::CLASS, S::Driver::NUM, S::NUM)
+    }
+
+    pub fn buffer(self: Pin<&Self>) -> Option<&[u8]> {
         if self.shared.is_some() {
             return None;
         }
-        self.get_ref().buffer_ref
+        Some(unsafe { &*self.get_ref().buffer })
     }
 
-    pub fn unallow(self: Pin<&mut Self>) -> Option<&B> {
+    /// Returns the buffer if it is not shared and was borrowed through
+    /// `with_mut`.
+    pub fn buffer_mut(self: Pin<&mut Self>) -> Option<&mut [u8]> {
+        if self.shared.is_some() || !self.writable {
+            return None;
+        }
+        Some(unsafe { &mut *self.buffer })
+    }
+
+    pub fn unallow(self: Pin<&mut Self>) -> &[u8] {
         let this = unsafe { Pin::into_inner_unchecked(self) };
+        unshare_if_shared(&mut this.shared, this.buffer);
+        unsafe { &*this.buffer }
+    }
+}
+
+/// An upcall registration that tracks whether it is subscribed and the
+/// subscribe ID at runtime.
+pub struct Subscription<U: Upcall> {
+    _pinned: PhantomPinned,
+    subscribed: Option<SubscribeInfo>,
+    upcall: U,
+}
+
+struct SubscribeInfo {
+    driver_num: u32,
+    subscribe_num: u32,
+}
+
+impl<U: Default + Upcall> Default for Subscription<U> {
+    fn default() -> Subscription<U> {
+        Subscription {
+            _pinned: PhantomPinned,
+            subscribed: None,
+            upcall: Default::default(),
+        }
+    }
+}
+
+impl<U: Upcall> From<U> for Subscription<U> {
+    fn from(upcall: U) -> Subscription<U> {
+        Subscription {
+            _pinned: PhantomPinned,
+            subscribed: None,
+            upcall,
+        }
+    }
+}
 
-        unshare_if_shared(&mut this.shared);
-        this.buffer_ref
+impl<U: Upcall> Drop for Subscription<U> {
+    fn drop(&mut self) {
+        unsubscribe_if_subscribed(&mut self.subscribed);
+    }
+}
+
+impl<U: Upcall> Subscription<U> {
+    pub fn subscribe(
+        self: Pin<&mut Self>,
+        driver_num: u32,
+        subscribe_num: u32,
+    ) -> Result<(), ErrorCode> {
+        if self.subscribed.is_some() {
+            return Err(ErrorCode::Already);
+        }
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        unsafe { subscribe_inner(driver_num, subscribe_num, &this.upcall) }?;
+        this.subscribed = Some(SubscribeInfo {
+            driver_num,
+            subscribe_num,
+        });
+        Ok(())
+    }
+
+    pub fn unsubscribe(self: Pin<&mut Self>) {
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        unsubscribe_if_subscribed(&mut this.subscribed);
+    }
+
+    pub fn upcall(self: Pin<&Self>) -> &U {
+        &self.get_ref().upcall
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    AllowSubscription<DRIVER_NUM, SUBSCRIBE_NUM> for Subscription<U>
+{
+    type Upcall = U;
+
+    fn subscribe(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        Subscription::subscribe(self, DRIVER_NUM, SUBSCRIBE_NUM)
+    }
+
+    fn unsubscribe(self: Pin<&mut Self>) {
+        Subscription::unsubscribe(self);
+    }
+
+    fn upcall(self: Pin<&Self>) -> &U {
+        Subscription::upcall(self)
+    }
+}
+
+/// The `full_dynamic` implementation, for code that is generic over
+/// `Implementation`. Its Buffers and Subscriptions take their IDs from the
+/// trait's type parameters.
+pub enum FullDynamicImpl {}
+
+impl Implementation for FullDynamicImpl {
+    type Buffer<S: AllowSlot, B: FromBytes + IntoBytes> = Buffer<B>;
+    type Subscription<U: Default + Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> =
+        Subscription<U>;
+}
+
+#[inline(never)]
+fn unsubscribe_if_subscribed(subscribed: &mut Option<SubscribeInfo>) {
+    if let Some(info) = subscribed {
+        unsubscribe(info.driver_num, info.subscribe_num);
+        *subscribed = None;
     }
 }
 
@@ -183,21 +750,39 @@ impl<B: FromBytes + IntoBytes> StaticBuffer<B> {
 // inlined and unshare() should not, yet #[inline(never)] seems to have a
 // positive impact here? Unsure why.
 #[inline(never)]
-fn unshare_if_shared(shared: &mut Option<ShareInfo>) {
-    if let Some(info) = shared {
-        unshare(&info);
+fn unshare_if_shared(shared: &mut Option<AllowId>, buffer: *const [u8]) {
+    if let Some(id) = shared {
+        unshare(*id, buffer);
         *shared = None;
     }
 }
 
-fn unshare(info: &ShareInfo) {
-    unsafe {
-        dynamic_allow(
-            info.driver_num,
-            info.buffer_num,
-            null_mut(),
-            0,
-            info.allow_type,
-        );
+/// If the kernel returns a buffer `buffer` does not hold, this buffer had
+/// already been swapped out, and `check_swapped_out` shares the foreign buffer
+/// again.
+fn unshare(id: AllowId, buffer: *const [u8]) {
+    let (driver_num, buffer_num, allow_type) = (id.driver_num(), id.buffer_num(), id.allow_type());
+    let registers = unsafe { dynamic_allow(driver_num, buffer_num, null_mut(), 0, allow_type) };
+    if let Ok(returned) = decode_allow(registers)
+        && !holds(buffer, returned)
+    {
+        let _ = unsafe {
+            check_swapped_out(
+                driver_num,
+                buffer_num,
+                allow_type,
+                returned,
+                ReturnedBuffer::EMPTY,
+            )
+        };
     }
 }
+
+/// Whether `returned` is `buffer` or a range of it. A buffer may have been
+/// shared whole or as a range (`allow_range`), so any range within it that the
+/// kernel returns is this buffer's.
+fn holds(buffer: *const [u8], returned: ReturnedBuffer) -> bool {
+    let start = buffer as *const u8 as usize;
+    let address = returned.address as usize;
+    start <= address && address + returned.len <= start + buffer.len()
+}
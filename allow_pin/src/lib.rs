@@ -1,20 +1,199 @@
 #![no_std]
 
+use core::cell::Cell;
 use core::marker::{PhantomData, PhantomPinned};
 use core::mem::size_of_val;
 use core::pin::Pin;
-use core::ptr::null_mut;
-use zerocopy::{FromBytes, IntoBytes};
+use core::ptr::{null, null_mut};
+use zerocopy::{FromBytes, Immutable, IntoBytes};
 
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+extern crate std;
+
+pub mod allow_buffer;
+pub mod allow_set;
+pub mod blocking;
+pub mod driver;
 pub mod dynamic_type;
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+pub mod fake_kernel;
 pub mod full_dynamic;
 pub mod no_dynamic;
+#[cfg(feature = "registry")]
+mod registry;
+pub mod ring;
+pub mod share;
+pub mod streaming;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod typestate;
+pub mod uninit;
+pub mod view;
+
+pub use allow_buffer::{AllowBuffer, AllowSubscription, Implementation};
+pub use allow_set::AllowSet;
+pub use driver::{
+    Allow, AllowNumber, AllowRo, AllowRw, AllowSlot, AllowUserspaceReadable, Driver, DriverNum,
+    console, rng,
+};
+
+/// The error codes a system call can return, as defined by TRD104.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum ErrorCode {
+    Fail = 1,
+    Busy = 2,
+    Already = 3,
+    Off = 4,
+    Reserve = 5,
+    Invalid = 6,
+    Size = 7,
+    Cancel = 8,
+    NoMem = 9,
+    NoSupport = 10,
+    NoDevice = 11,
+    Uninstalled = 12,
+    NoAck = 13,
+    /// Not a kernel error code: the kernel returned a value that is not a
+    /// valid error code, or a return variant the system call cannot return.
+    BadRVal = 1024,
+    /// Not a kernel error code: an Allow call swapped out a buffer that another
+    /// owner believed was shared. The other buffer has been shared again.
+    ForeignBufferSwappedOut = 1025,
+}
+
+impl ErrorCode {
+    /// Converts an error code returned by the kernel. Values that are not
+    /// TRD104 error codes become `BadRVal`.
+    pub fn from_raw(raw: u32) -> ErrorCode {
+        match raw {
+            1 => ErrorCode::Fail,
+            2 => ErrorCode::Busy,
+            3 => ErrorCode::Already,
+            4 => ErrorCode::Off,
+            5 => ErrorCode::Reserve,
+            6 => ErrorCode::Invalid,
+            7 => ErrorCode::Size,
+            8 => ErrorCode::Cancel,
+            9 => ErrorCode::NoMem,
+            10 => ErrorCode::NoSupport,
+            11 => ErrorCode::NoDevice,
+            12 => ErrorCode::Uninstalled,
+            13 => ErrorCode::NoAck,
+            _ => ErrorCode::BadRVal,
+        }
+    }
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(error_code: ErrorCode) -> u32 {
+        error_code as u32
+    }
+}
+
+// Return variants (TRD104) used by the system calls in this crate.
+const FAILURE_2_U32: u32 = 2;
+const SUCCESS_2_U32: u32 = 130;
+
+/// A buffer the kernel returned from an Allow call: the previously-shared
+/// buffer on success, or the buffer that was passed in on failure.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReturnedBuffer {
+    pub address: *mut u8,
+    pub len: usize,
+}
+
+impl ReturnedBuffer {
+    /// What the kernel returns when no buffer was shared.
+    const EMPTY: ReturnedBuffer = ReturnedBuffer {
+        address: null_mut(),
+        len: 0,
+    };
+
+    /// What the kernel returns when `buffer` was shared.
+    fn of<B: ?Sized>(buffer: &B) -> ReturnedBuffer {
+        ReturnedBuffer {
+            address: buffer as *const B as *mut u8,
+            len: size_of_val(buffer),
+        }
+    }
 
-pub type ErrorCode = u32;
+    /// What the kernel returns when the borrowed slice `buffer` was shared.
+    fn of_slice(buffer: *const [u8]) -> ReturnedBuffer {
+        ReturnedBuffer {
+            address: buffer as *mut u8,
+            len: buffer.len(),
+        }
+    }
+}
 
-// Types to indicate RO allow versus RW allow.
+/// Decodes the registers returned by an Allow system call. Allow only returns
+/// Failure with 2 u32 and Success with 2 u32; any other variant is reported as
+/// `BadRVal` with an empty buffer.
+fn decode_allow(
+    (variant, r1, r2, r3): (u32, *mut u8, *mut u8, usize),
+) -> Result<ReturnedBuffer, (ErrorCode, ReturnedBuffer)> {
+    match variant {
+        SUCCESS_2_U32 => Ok(ReturnedBuffer {
+            address: r1,
+            len: r2.addr(),
+        }),
+        FAILURE_2_U32 => Err((
+            ErrorCode::from_raw(r1.addr() as u32),
+            ReturnedBuffer {
+                address: r2,
+                len: r3,
+            },
+        )),
+        _ => Err((ErrorCode::BadRVal, ReturnedBuffer::EMPTY)),
+    }
+}
+
+/// Checks the buffer an Allow call returned against `expected`, the buffer the
+/// caller believes was shared with the allow ID. If the call swapped out some
+/// other buffer, that buffer's owner still believes it is shared, so it is
+/// shared again (displacing the caller's buffer, if any) and
+/// `ForeignBufferSwappedOut` is returned.
+///
+/// The displaced buffer is restored rather than having its owner's tracking
+/// updated because `dynamic_type` and `full_dynamic` keep no table from allow
+/// ID to owner: the returned address is all that identifies the owner, and
+/// writing through it would alias the owner's `Pin`. If sharing it again
+/// fails, the caller's buffer is unshared so that neither buffer is left
+/// shared unexpectedly, and that failure is returned instead. The owner then
+/// believes its buffer is shared when it is not, which only costs it a
+/// redundant unallow.
+unsafe fn check_swapped_out(
+    driver_num: u32,
+    allow_num: u32,
+    allow_type: DynamicType,
+    returned: ReturnedBuffer,
+    expected: ReturnedBuffer,
+) -> Result<(), ErrorCode> {
+    if returned == expected || returned.address.is_null() {
+        return Ok(());
+    }
+    let registers = unsafe {
+        dynamic_allow(
+            driver_num,
+            allow_num,
+            returned.address,
+            returned.len,
+            allow_type,
+        )
+    };
+    if let Err((error, _)) = decode_allow(registers) {
+        unsafe { dynamic_allow(driver_num, allow_num, null_mut(), 0, allow_type) };
+        return Err(error);
+    }
+    Err(ErrorCode::ForeignBufferSwappedOut)
+}
+
+// Types to indicate RO allow versus RW allow versus userspace-readable allow
+// (RW allow that userspace may read while it is shared).
 pub enum StaticRo {}
 pub enum StaticRw {}
+pub enum StaticUserspaceReadable {}
 // Should this be sealed?
 pub trait StaticType {
     const CLASS: DynamicType;
@@ -25,11 +204,37 @@ impl StaticType for StaticRo {
 impl StaticType for StaticRw {
     const CLASS: DynamicType = DynamicType::Rw;
 }
+impl StaticType for StaticUserspaceReadable {
+    const CLASS: DynamicType = DynamicType::UserspaceReadable;
+}
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum DynamicType {
     Rw = 3,
     Ro = 4,
+    UserspaceReadable = 7,
+}
+
+/// Handles upcalls delivered to a `Subscription`. The kernel only ever accesses
+/// the handler through a shared reference, so it may be read while subscribed.
+pub trait Upcall {
+    fn upcall(&self, args: [u32; 3]);
+}
+
+/// Records the arguments of the most recent upcall.
+impl Upcall for Cell<Option<[u32; 3]>> {
+    fn upcall(&self, args: [u32; 3]) {
+        self.set(Some(args));
+    }
+}
+
+/// The signature of the upcall function pointer passed to Subscribe.
+pub type UpcallFn = unsafe extern "C" fn(u32, u32, u32, *const ());
+
+/// The function pointer that is passed to the kernel by every `Subscription`.
+/// `data` is the address of the `Subscription`'s `U`.
+unsafe extern "C" fn kernel_upcall<U: Upcall>(arg0: u32, arg1: u32, arg2: u32, data: *const ()) {
+    unsafe { &*data.cast::<U>() }.upcall([arg0, arg1, arg2]);
 }
 
 /// Command system call, used mostly to clobber registers to make things more
@@ -37,9 +242,11 @@ pub enum DynamicType {
 /// the compiler was able to reuse registers between Allow calls in an
 /// unrealistic way).
 pub fn command(driver_num: u32, allow_num: u32, arg0: u32, arg1: u32) -> Result<(), ErrorCode> {
-    let [r0, r1];
+    #[cfg(feature = "trace")]
+    trace::record_command(driver_num, allow_num);
+    let [r0, r1]: [u32; 2];
+    #[cfg(target_arch = "arm")]
     unsafe {
-        #[cfg(target_arch = "arm")]
         core::arch::asm!(
             "svc 2",
             inlateout("r0") driver_num => r0,
@@ -48,23 +255,98 @@ pub fn command(driver_num: u32, allow_num: u32, arg0: u32, arg1: u32) -> Result<
             inlateout("r3") arg1 => _,
             options(preserves_flags, nomem, nostack),
         );
-        #[cfg(target_arch = "riscv32")]
-        core::arch::asm!(
-            "ecall",
-            inlateout("a0") driver_num => r0,
-            inlateout("a1") allow_num => r1,
-            inlateout("a2") arg0 => _,
-            inlateout("a3") arg1 => _,
-            in("a4") 2,
-            options(preserves_flags, nomem, nostack),
-        );
     }
-    match r0 < 128 {
-        false => Ok(()),
-        true => Err(r1),
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    {
+        let (variant, value): (usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                inlateout("a0") driver_num as usize => variant,
+                inlateout("a1") allow_num as usize => value,
+                inlateout("a2") arg0 as usize => _,
+                inlateout("a3") arg1 as usize => _,
+                in("a4") 2usize,
+                options(preserves_flags, nomem, nostack),
+            );
+        }
+        [r0, r1] = [variant as u32, value as u32];
     }
+    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+    {
+        [r0, r1] = fake_kernel::command(driver_num, allow_num, arg0, arg1);
+    }
+    // Failure variants are 0-3 and carry the error code in r1; success
+    // variants are 128-133.
+    match r0 {
+        0..=3 => Err(ErrorCode::from_raw(r1)),
+        128..=133 => Ok(()),
+        _ => Err(ErrorCode::BadRVal),
+    }
+}
+
+/// Yield-Wait system call: blocks until the kernel has run an upcall. The
+/// upcall runs on this stack before Yield returns, so this is treated as a call
+/// to a function that may read and write any memory.
+pub fn yield_wait() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!("svc 0", in("r0") 1u32, clobber_abi("C"));
+    }
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        core::arch::asm!("ecall", in("a0") 1usize, in("a4") 0usize, clobber_abi("C"));
+    }
+    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+    fake_kernel::yield_wait();
 }
 
+/// Yield-WaitFor system call: blocks until the kernel schedules the upcall with
+/// the given subscribe ID, and returns its arguments. The upcall itself is not
+/// run, and no other upcall runs during this call.
+pub fn yield_wait_for(driver_num: u32, subscribe_num: u32) -> [u32; 3] {
+    #[cfg(target_arch = "arm")]
+    {
+        let (r0, r1, r2);
+        unsafe {
+            core::arch::asm!(
+                "svc 0",
+                inlateout("r0") 2u32 => r0,
+                inlateout("r1") driver_num => r1,
+                inlateout("r2") subscribe_num => r2,
+                clobber_abi("C"),
+            );
+        }
+        [r0, r1, r2]
+    }
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    {
+        let (a0, a1, a2): (usize, usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                inlateout("a0") 2usize => a0,
+                inlateout("a1") driver_num as usize => a1,
+                inlateout("a2") subscribe_num as usize => a2,
+                in("a4") 0usize,
+                clobber_abi("C"),
+            );
+        }
+        [a0 as u32, a1 as u32, a2 as u32]
+    }
+    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+    fake_kernel::yield_wait_for(driver_num, subscribe_num)
+}
+
+// RISC-V system call registers are a full word wide, so on riscv64 a `u32`
+// passed directly to `asm!` would leave the upper half of its register
+// undefined. The RISC-V system calls therefore carry three kinds of value
+// separately: `u32` arguments and results (numbers, error codes and return
+// variants) are widened to and truncated from `usize`; lengths are `usize`;
+// and anything that may be an address stays a `*mut u8`, so it keeps its
+// provenance. A CHERI port only needs to change that pointer type to a
+// capability. On riscv32 the conversions are no-ops.
+
 /// Raw Allow system call with a runtime-specified allow type.
 unsafe fn dynamic_allow(
     driver_num: u32,
@@ -72,32 +354,81 @@ unsafe fn dynamic_allow(
     address: *mut u8,
     len: usize,
     allow_type: DynamicType,
+) -> (u32, *mut u8, *mut u8, usize) {
+    #[cfg(feature = "trace")]
+    trace::record_allow(
+        trace::Kind::DynamicAllow,
+        driver_num,
+        allow_num,
+        allow_type,
+        address,
+        len,
+    );
+    unsafe { untraced_dynamic_allow(driver_num, allow_num, address, len, allow_type) }
+}
+
+/// Raw Allow system call with a `const` allow type.
+unsafe fn static_allow<T: StaticType>(
+    driver_num: u32,
+    allow_num: u32,
+    address: *mut u8,
+    len: usize,
+) -> (u32, *mut u8, *mut u8, usize) {
+    #[cfg(feature = "trace")]
+    trace::record_allow(
+        trace::Kind::StaticAllow,
+        driver_num,
+        allow_num,
+        T::CLASS,
+        address,
+        len,
+    );
+    unsafe { untraced_static_allow::<T>(driver_num, allow_num, address, len) }
+}
+
+// The system calls behind `dynamic_allow` and `static_allow`, which call each
+// other depending on the architecture, so each call is only traced once.
+
+unsafe fn untraced_dynamic_allow(
+    driver_num: u32,
+    allow_num: u32,
+    address: *mut u8,
+    len: usize,
+    allow_type: DynamicType,
 ) -> (u32, *mut u8, *mut u8, usize) {
     #[cfg(target_arch = "arm")]
     match allow_type {
-        DynamicType::Ro => unsafe { static_allow::<StaticRo>(driver_num, allow_num, address, len) },
-        DynamicType::Rw => unsafe { static_allow::<StaticRw>(driver_num, allow_num, address, len) },
+        DynamicType::Ro => unsafe {
+            untraced_static_allow::<StaticRo>(driver_num, allow_num, address, len)
+        },
+        DynamicType::Rw => unsafe {
+            untraced_static_allow::<StaticRw>(driver_num, allow_num, address, len)
+        },
+        DynamicType::UserspaceReadable => unsafe {
+            untraced_static_allow::<StaticUserspaceReadable>(driver_num, allow_num, address, len)
+        },
     }
-    #[cfg(target_arch = "riscv32")]
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
     {
-        let (variant, r1, r2, r3);
+        let (variant, r1, r2, r3): (usize, *mut u8, *mut u8, usize);
         unsafe {
             core::arch::asm!(
                 "ecall",
-                inlateout("a0") driver_num => variant,
-                inlateout("a1") allow_num => r1,
+                inlateout("a0") driver_num as usize => variant,
+                inlateout("a1") allow_num as usize => r1,
                 inlateout("a2") address => r2,
                 inlateout("a3") len => r3,
-                in("a4") allow_type as u32,
+                in("a4") allow_type as usize,
                 options(preserves_flags, nostack),
             );
         }
-        (variant, r1, r2, r3)
+        (variant as u32, r1, r2, r3)
     }
+    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+    fake_kernel::allow(driver_num, allow_num, address, len, allow_type)
 }
 
-/// Raw Allow system call with a `const` allow type.
-unsafe fn static_allow<T: StaticType>(
+unsafe fn untraced_static_allow<T: StaticType>(
     driver_num: u32,
     allow_num: u32,
     address: *mut u8,
@@ -119,13 +450,120 @@ unsafe fn static_allow<T: StaticType>(
         }
         (variant, r1, r2, r3)
     }
-    #[cfg(target_arch = "riscv32")]
+    #[cfg(not(target_arch = "arm"))]
+    unsafe {
+        untraced_dynamic_allow(driver_num, allow_num, address, len, T::CLASS)
+    }
+}
+
+/// Copies the bytes at `src` into `dest` using volatile reads, for buffers the
+/// kernel may write to at any time (userspace-readable allow). The copy may be
+/// torn if the kernel writes while it is in progress.
+unsafe fn read_volatile_bytes(src: *const u8, dest: &mut [u8]) {
+    for (i, byte) in dest.iter_mut().enumerate() {
+        *byte = unsafe { src.add(i).read_volatile() };
+    }
+}
+
+/// The number of copies `read_untorn` makes before giving up.
+const UNTORN_READ_ATTEMPTS: usize = 4;
+
+/// Reads a `B` that the kernel may be updating. Repeats the read until two
+/// consecutive copies agree, so the result is not torn by a kernel write that
+/// happened partway through a copy. Two equal copies could still straddle two
+/// kernel writes that left the same bytes, which is taken as untorn. Returns
+/// `None` if the kernel changed the value between every pair of the
+/// `UNTORN_READ_ATTEMPTS` copies, rather than retrying forever.
+unsafe fn read_untorn<B: FromBytes + IntoBytes + Immutable>(src: *const B) -> Option<B> {
+    let mut current = B::new_zeroed();
+    unsafe { read_volatile_bytes(src.cast(), current.as_mut_bytes()) };
+    for _ in 1..UNTORN_READ_ATTEMPTS {
+        let mut next = B::new_zeroed();
+        unsafe { read_volatile_bytes(src.cast(), next.as_mut_bytes()) };
+        if next.as_bytes() == current.as_bytes() {
+            return Some(next);
+        }
+        current = next;
+    }
+    None
+}
+
+/// Raw Subscribe system call. Passing a null `upcall` and `data` unsubscribes.
+/// Returns the return variant and r1.
+unsafe fn subscribe(
+    driver_num: u32,
+    subscribe_num: u32,
+    upcall: *const (),
+    data: *const (),
+) -> (u32, u32) {
+    let (variant, r1);
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!(
+            "svc 1",
+            inlateout("r0") driver_num => variant,
+            inlateout("r1") subscribe_num => r1,
+            inlateout("r2") upcall => _,
+            inlateout("r3") data => _,
+            options(preserves_flags, nostack),
+        );
+    }
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    {
+        let (raw_variant, raw_r1): (usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                inlateout("a0") driver_num as usize => raw_variant,
+                inlateout("a1") subscribe_num as usize => raw_r1,
+                inlateout("a2") upcall => _,
+                inlateout("a3") data => _,
+                in("a4") 1usize,
+                options(preserves_flags, nostack),
+            );
+        }
+        (variant, r1) = (raw_variant as u32, raw_r1 as u32);
+    }
+    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+    {
+        (variant, r1) = fake_kernel::subscribe(driver_num, subscribe_num, upcall, data);
+    }
+    (variant, r1)
+}
+
+/// Registers `U`'s upcall with the given subscribe ID. `upcall` must outlive
+/// the subscription.
+unsafe fn subscribe_inner<U: Upcall>(
+    driver_num: u32,
+    subscribe_num: u32,
+    upcall: &U,
+) -> Result<(), ErrorCode> {
+    let (variant, r1) = unsafe {
+        subscribe(
+            driver_num,
+            subscribe_num,
+            kernel_upcall::<U> as UpcallFn as *const (),
+            upcall as *const U as *const (),
+        )
+    };
+    match variant {
+        SUCCESS_2_U32 => Ok(()),
+        FAILURE_2_U32 => Err(ErrorCode::from_raw(r1)),
+        _ => Err(ErrorCode::BadRVal),
+    }
+}
+
+/// Replaces the upcall registered with the given subscribe ID with the null
+/// upcall. No error handling is needed for the same reason as unallow.
+fn unsubscribe(driver_num: u32, subscribe_num: u32) {
     unsafe {
-        dynamic_allow(driver_num, allow_num, address, len, T::CLASS)
+        subscribe(driver_num, subscribe_num, null(), null());
     }
 }
 
-/// Required for the examples to compile.
+/// Required for the examples to compile. On the host, `std` provides the panic
+/// handler instead.
+#[cfg(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64"))]
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     loop {}
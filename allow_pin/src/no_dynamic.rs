@@ -1,44 +1,37 @@
 //! A maximally-static implementation: no dynamic RO/RW, no dynamic ID. Every
 //! operation that requires the buffer to be unshared unconditionally unshares it.
+//! With the `registry` feature, a global table records which Buffer holds each
+//! allow ID, and those operations only unshare if this Buffer is the holder.
 
+use crate::view::{self, View, ViewMut};
 use crate::*;
+use core::pin::pin;
 use core::ptr;
+use zerocopy::KnownLayout;
 
-pub use crate::{StaticRo, StaticRw};
+pub use crate::{AllowRo, AllowRw, AllowSlot, AllowUserspaceReadable, Driver};
 
-pub struct Buffer<
-    P: StaticType,
-    B: FromBytes + IntoBytes + ?Sized,
-    const DRIVER_NUM: u32,
-    const BUFFER_NUM: u32,
-> {
-    _perms: PhantomData<P>,
+/// A buffer that can be shared with allow slot `S`.
+pub struct Buffer<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> {
+    _slot: PhantomData<S>,
     _pinned: PhantomPinned,
     buffer: B,
 }
 
-impl<
-    P: StaticType,
-    B: Default + FromBytes + IntoBytes,
-    const DRIVER_NUM: u32,
-    const BUFFER_NUM: u32,
-> Default for Buffer<P, B, DRIVER_NUM, BUFFER_NUM>
-{
-    fn default() -> Buffer<P, B, DRIVER_NUM, BUFFER_NUM> {
+impl<S: AllowSlot, B: Default + FromBytes + IntoBytes> Default for Buffer<S, B> {
+    fn default() -> Buffer<S, B> {
         Buffer {
-            _perms: PhantomData,
+            _slot: PhantomData,
             _pinned: PhantomPinned,
             buffer: Default::default(),
         }
     }
 }
 
-impl<P: StaticType, B: FromBytes + IntoBytes, const DRIVER_NUM: u32, const BUFFER_NUM: u32> From<B>
-    for Buffer<P, B, DRIVER_NUM, BUFFER_NUM>
-{
-    fn from(buffer: B) -> Buffer<P, B, DRIVER_NUM, BUFFER_NUM> {
+impl<S: AllowSlot, B: FromBytes + IntoBytes> From<B> for Buffer<S, B> {
+    fn from(buffer: B) -> Buffer<S, B> {
         Buffer {
-            _perms: PhantomData,
+            _slot: PhantomData,
             _pinned: PhantomPinned,
             buffer,
         }
@@ -46,30 +39,31 @@ impl<P: StaticType, B: FromBytes + IntoBytes, const DRIVER_NUM: u32, const BUFFE
 }
 
 // Possible surprising semantics: A Buffer that is created but never allowed
-// will still clear its allow ID on drop!
-impl<P: StaticType, B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM: u32>
-    Drop for Buffer<P, B, DRIVER_NUM, BUFFER_NUM>
-{
+// will still clear its allow ID on drop (unless the `registry` feature is
+// enabled)!
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> Drop for Buffer<S, B> {
     fn drop(&mut self) {
-        unshare::<P>(DRIVER_NUM, BUFFER_NUM);
+        unshare_if_holder::<S>(ptr::from_ref(self).cast());
     }
 }
 
 // Read-Only methods
-impl<B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM: u32>
-    Buffer<StaticRo, B, DRIVER_NUM, BUFFER_NUM>
-{
+impl<D: Driver, const NUM: u32, B: FromBytes + IntoBytes + ?Sized> Buffer<AllowRo<D, NUM>, B> {
     pub fn allow_ro(self: Pin<&Self>) -> Result<(), ErrorCode> {
         unsafe {
-            allow_inner::<StaticRo>(
-                DRIVER_NUM,
-                BUFFER_NUM,
-                ptr::slice_from_raw_parts(
-                    (&raw const self.buffer).cast(),
-                    size_of_val(&self.buffer),
-                ),
-            )
-        }
+            allow_inner::<AllowRo<D, NUM>>(ptr::slice_from_raw_parts(
+                (&raw const self.buffer).cast(),
+                size_of_val(&self.buffer),
+            ))
+        }?;
+        #[cfg(feature = "registry")]
+        registry::set_owner(
+            D::NUM,
+            NUM,
+            StaticRo::CLASS,
+            ptr::from_ref(self.get_ref()).cast(),
+        );
+        Ok(())
     }
 
     pub fn buffer(self: Pin<&Self>) -> &B {
@@ -80,77 +74,174 @@ impl<B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM:
     /// buffer.
     pub fn replace_with_ro<OB: FromBytes + IntoBytes + ?Sized>(
         self: Pin<&Self>,
-        new: Pin<&Buffer<StaticRo, OB, DRIVER_NUM, BUFFER_NUM>>,
+        new: Pin<&Buffer<AllowRo<D, NUM>, OB>>,
     ) -> (&B, Result<(), ErrorCode>) {
         (self.buffer(), new.allow_ro())
     }
 
     /// Allows `new`, un-allowing `self`. Returns a mutable reference to
-    /// `self`'s buffer.
+    /// `self`'s buffer. If allowing `new` fails, `self` is unallowed instead.
     pub fn replace_with_mut_ro<OB: FromBytes + IntoBytes + ?Sized>(
         self: Pin<&mut Self>,
-        new: Pin<&Buffer<StaticRo, OB, DRIVER_NUM, BUFFER_NUM>>,
+        new: Pin<&Buffer<AllowRo<D, NUM>, OB>>,
     ) -> (&mut B, Result<(), ErrorCode>) {
+        let result = new.allow_ro();
+        if result.is_err() {
+            self.as_ref().unallow_ref();
+        }
         (
             &mut unsafe { Pin::into_inner_unchecked(self) }.buffer,
-            new.allow_ro(),
+            result,
         )
     }
 }
 
 // Read-Write methods
-impl<B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM: u32>
-    Buffer<StaticRw, B, DRIVER_NUM, BUFFER_NUM>
+impl<D: Driver, const NUM: u32, B: FromBytes + IntoBytes + ?Sized> Buffer<AllowRw<D, NUM>, B> {
+    pub fn buffer(self: Pin<&Self>) -> &B {
+        unshare_if_holder::<AllowRw<D, NUM>>(ptr::from_ref(self.get_ref()).cast());
+        &self.get_ref().buffer
+    }
+}
+
+// Userspace-Readable methods. The kernel may write to the buffer while it is
+// shared, so these take `Pin<&mut Self>` to avoid handing out a shared
+// reference to memory the kernel is modifying.
+impl<D: Driver, const NUM: u32, B: FromBytes + IntoBytes + ?Sized>
+    Buffer<AllowUserspaceReadable<D, NUM>, B>
 {
     pub fn buffer(self: Pin<&Self>) -> &B {
-        unshare::<StaticRw>(DRIVER_NUM, BUFFER_NUM);
+        unshare_if_holder::<AllowUserspaceReadable<D, NUM>>(ptr::from_ref(self.get_ref()).cast());
         &self.get_ref().buffer
     }
+
+    /// Copies `dest.len()` bytes starting at `offset` into `dest` without
+    /// unallowing. The copy may be torn by a concurrent kernel write.
+    pub fn read_volatile(self: Pin<&mut Self>, offset: usize, dest: &mut [u8]) {
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(
+            offset
+                .checked_add(dest.len())
+                .is_some_and(|end| end <= size_of_val(&this.buffer))
+        );
+        unsafe { read_volatile_bytes((&raw const this.buffer).cast::<u8>().add(offset), dest) }
+    }
+
+    /// Copies an `H` from the start of the buffer without unallowing, retrying
+    /// until the read is not torn by a concurrent kernel write.
+    pub fn read_header<H: FromBytes + IntoBytes + Immutable>(
+        self: Pin<&mut Self>,
+    ) -> Result<H, ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        unsafe { view::read_header((&raw const this.buffer).cast(), size_of_val(&this.buffer)) }
+    }
 }
 
-// Methods that exist in both Read-Only and Read-Write Allow.
-impl<P: StaticType, B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, const BUFFER_NUM: u32>
-    Buffer<P, B, DRIVER_NUM, BUFFER_NUM>
+impl<D: Driver, const NUM: u32, B: FromBytes + IntoBytes + Immutable>
+    Buffer<AllowUserspaceReadable<D, NUM>, B>
 {
+    /// Reads the buffer without unallowing, retrying until the read is not
+    /// torn by a concurrent kernel write. Returns `None` if the kernel kept
+    /// changing it.
+    pub fn read(self: Pin<&mut Self>) -> Option<B> {
+        unsafe { read_untorn(&raw const self.get_unchecked_mut().buffer) }
+    }
+}
+
+// Methods that exist in both Read-Only and Read-Write Allow.
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> Buffer<S, B> {
     pub fn allow(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
         unsafe {
-            let this = self.get_unchecked_mut();
-            allow_inner::<P>(
-                DRIVER_NUM,
-                BUFFER_NUM,
-                ptr::slice_from_raw_parts_mut(
-                    (&raw mut this.buffer).cast(),
-                    size_of_val(&this.buffer),
-                ),
-            )
-        }
+            allow_inner::<S>(ptr::slice_from_raw_parts_mut(
+                (&raw mut this.buffer).cast(),
+                size_of_val(&this.buffer),
+            ))
+        }?;
+        #[cfg(feature = "registry")]
+        registry::set_owner(
+            S::Driver::NUM,
+            S::NUM,
+            S::Class::CLASS,
+            ptr::from_mut(this).cast(),
+        );
+        Ok(())
     }
 
     // Possible surprising semantics: Retrieving the buffer performs an unallow,
-    // even if a different buffer is shared with this allow ID! (applies to
-    // buffer() as well).
+    // even if a different buffer is shared with this allow ID (unless the
+    // `registry` feature is enabled)! (applies to buffer() as well).
     pub fn buffer_mut(self: Pin<&mut Self>) -> &mut B {
-        unshare::<P>(DRIVER_NUM, BUFFER_NUM);
+        let this = unsafe { Pin::into_inner_unchecked(self) };
+        unshare_if_holder::<S>(ptr::from_mut(this).cast());
+        &mut this.buffer
+    }
+
+    /// Views the buffer as an `H` followed by a payload, unallowing it like
+    /// `buffer_mut`.
+    pub fn view<H: FromBytes + KnownLayout + Immutable>(
+        self: Pin<&mut Self>,
+    ) -> Result<View<'_, H>, ErrorCode>
+    where
+        B: Immutable,
+    {
+        let buffer: &B = self.buffer_mut();
+        view::parse(buffer.as_bytes())
+    }
+
+    /// Views the buffer as a mutable `H` followed by a payload, unallowing it
+    /// like `buffer_mut`.
+    pub fn view_mut<H: FromBytes + IntoBytes + KnownLayout + Immutable>(
+        self: Pin<&mut Self>,
+    ) -> Result<ViewMut<'_, H>, ErrorCode> {
+        view::parse_mut(self.buffer_mut().as_mut_bytes())
+    }
+
+    /// Writes `header` to the start of the buffer, for example before sharing
+    /// it read-only, and returns the payload bytes after it. Unallows the
+    /// buffer like `buffer_mut`.
+    pub fn write_header<H: IntoBytes + KnownLayout + Immutable>(
+        self: Pin<&mut Self>,
+        header: H,
+    ) -> Result<&mut [u8], ErrorCode> {
+        view::write(self.buffer_mut().as_mut_bytes(), header)
+    }
+
+    /// Unshares the allow ID if this Buffer may be holding it, for callers that
+    /// need an RO buffer unshared (`buffer` leaves it shared).
+    pub(crate) fn unallow_ref(self: Pin<&Self>) {
+        unshare_if_holder::<S>(ptr::from_ref(self.get_ref()).cast());
+    }
+
+    /// Returns the buffer without unallowing, for callers that know this
+    /// Buffer is not the one shared with its allow ID.
+    pub(crate) fn buffer_mut_unshared(self: Pin<&mut Self>) -> &mut B {
         &mut unsafe { Pin::into_inner_unchecked(self) }.buffer
     }
 
     /// Allows `new`, un-allowing `self`. Returns a reference to `self`'s
-    /// buffer.
+    /// buffer. If allowing `new` fails, `self` is unallowed instead.
     pub fn replace_with<OB: FromBytes + IntoBytes + ?Sized>(
         self: Pin<&Self>,
-        new: Pin<&mut Buffer<P, OB, DRIVER_NUM, BUFFER_NUM>>,
+        new: Pin<&mut Buffer<S, OB>>,
     ) -> (&B, Result<(), ErrorCode>) {
         let result = new.allow();
+        if result.is_err() {
+            self.unallow_ref();
+        }
         (&self.get_ref().buffer, result)
     }
 
     /// Allows `new`, un-allowing `self`. Returns a mutable reference to
-    /// `self`'s buffer.
+    /// `self`'s buffer. If allowing `new` fails, `self` is unallowed instead.
     pub fn replace_with_mut<OB: FromBytes + IntoBytes + ?Sized>(
         self: Pin<&mut Self>,
-        new: Pin<&mut Buffer<P, OB, DRIVER_NUM, BUFFER_NUM>>,
+        new: Pin<&mut Buffer<S, OB>>,
     ) -> (&mut B, Result<(), ErrorCode>) {
         let result = new.allow();
+        if result.is_err() {
+            self.as_ref().unallow_ref();
+        }
         (
             &mut unsafe { Pin::into_inner_unchecked(self) }.buffer,
             result,
@@ -158,26 +249,244 @@ impl<P: StaticType, B: FromBytes + IntoBytes + ?Sized, const DRIVER_NUM: u32, co
     }
 }
 
-unsafe fn allow_inner<P: StaticType>(
-    driver_num: u32,
-    buffer_num: u32,
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> AllowBuffer<S> for Buffer<S, B> {
+    type Data = B;
+
+    fn allow(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        Buffer::allow(self)
+    }
+
+    fn unallow(self: Pin<&mut Self>) -> &mut B {
+        self.buffer_mut()
+    }
+
+    /// Swaps the buffers in a single Allow call.
+    fn replace_with<'a>(
+        self: Pin<&'a mut Self>,
+        other: Pin<&mut Self>,
+    ) -> (&'a mut B, Result<(), ErrorCode>) {
+        self.replace_with_mut(other)
+    }
+}
+
+/// A Buffer that borrows a caller-owned slice instead of owning its data, so a
+/// library can share data it was handed without copying it into a `Buffer`.
+///
+/// Leaking a `BorrowedBuffer` would end the borrow while the kernel may still
+/// hold the slice, so it cannot be constructed directly: `with` and `with_mut`
+/// pin it for the duration of a closure and drop it (unallowing) afterwards.
+pub struct BorrowedBuffer<'a, S: AllowSlot> {
+    _slot: PhantomData<S>,
+    _pinned: PhantomPinned,
+    _borrow: PhantomData<&'a mut [u8]>,
+    buffer: *mut [u8],
+}
+
+impl<S: AllowSlot> Drop for BorrowedBuffer<'_, S> {
+    fn drop(&mut self) {
+        unshare_if_holder::<S>(ptr::from_ref(self).cast());
+    }
+}
+
+impl<'a, S: AllowSlot> BorrowedBuffer<'a, S> {
+    fn scope<R>(buffer: *mut [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        f(pin!(BorrowedBuffer {
+            _slot: PhantomData,
+            _pinned: PhantomPinned,
+            _borrow: PhantomData,
+            buffer,
+        }))
+    }
+
+    pub fn allow(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        unsafe { allow_inner::<S>(self.buffer) }?;
+        #[cfg(feature = "registry")]
+        registry::set_owner(
+            S::Driver::NUM,
+            S::NUM,
+            S::Class::CLASS,
+            ptr::from_ref(self.as_ref().get_ref()).cast(),
+        );
+        Ok(())
+    }
+}
+
+// Read-Only methods
+impl<'a, D: Driver, const NUM: u32> BorrowedBuffer<'a, AllowRo<D, NUM>> {
+    /// Calls `f` with a pinned BorrowedBuffer for `buffer`, unallowing it when
+    /// `f` returns.
+    pub fn with<R>(buffer: &'a [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        Self::scope(ptr::from_ref(buffer).cast_mut(), f)
+    }
+
+    pub fn buffer(self: Pin<&Self>) -> &[u8] {
+        unsafe { &*self.buffer }
+    }
+}
+
+// Read-Write methods
+impl<'a, D: Driver, const NUM: u32> BorrowedBuffer<'a, AllowRw<D, NUM>> {
+    /// Calls `f` with a pinned BorrowedBuffer for `buffer`, unallowing it when
+    /// `f` returns.
+    pub fn with_mut<R>(buffer: &'a mut [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        Self::scope(buffer, f)
+    }
+
+    pub fn buffer(self: Pin<&Self>) -> &[u8] {
+        unshare_if_holder::<AllowRw<D, NUM>>(ptr::from_ref(self.get_ref()).cast());
+        unsafe { &*self.buffer }
+    }
+
+    pub fn buffer_mut(self: Pin<&mut Self>) -> &mut [u8] {
+        unshare_if_holder::<AllowRw<D, NUM>>(ptr::from_ref(self.as_ref().get_ref()).cast());
+        unsafe { &mut *self.buffer }
+    }
+}
+
+// Userspace-Readable methods
+impl<'a, D: Driver, const NUM: u32> BorrowedBuffer<'a, AllowUserspaceReadable<D, NUM>> {
+    /// Calls `f` with a pinned BorrowedBuffer for `buffer`, unallowing it when
+    /// `f` returns.
+    pub fn with_mut<R>(buffer: &'a mut [u8], f: impl FnOnce(Pin<&mut Self>) -> R) -> R {
+        Self::scope(buffer, f)
+    }
+
+    pub fn buffer(self: Pin<&Self>) -> &[u8] {
+        unshare_if_holder::<AllowUserspaceReadable<D, NUM>>(ptr::from_ref(self.get_ref()).cast());
+        unsafe { &*self.buffer }
+    }
+
+    pub fn buffer_mut(self: Pin<&mut Self>) -> &mut [u8] {
+        unshare_if_holder::<AllowUserspaceReadable<D, NUM>>(
+            ptr::from_ref(self.as_ref().get_ref()).cast(),
+        );
+        unsafe { &mut *self.buffer }
+    }
+}
+
+/// An upcall registration. Like `Buffer`, this does not track whether it is
+/// subscribed, so it unconditionally unsubscribes on drop.
+pub struct Subscription<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> {
+    _pinned: PhantomPinned,
+    upcall: U,
+}
+
+impl<U: Default + Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> Default
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn default() -> Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM> {
+        Subscription {
+            _pinned: PhantomPinned,
+            upcall: Default::default(),
+        }
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> From<U>
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn from(upcall: U) -> Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM> {
+        Subscription {
+            _pinned: PhantomPinned,
+            upcall,
+        }
+    }
+}
+
+// Same surprising semantics as Buffer: a Subscription that is never subscribed
+// will still clear its subscribe ID on drop.
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> Drop
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn drop(&mut self) {
+        unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    pub fn subscribe(self: Pin<&Self>) -> Result<(), ErrorCode> {
+        unsafe { subscribe_inner(DRIVER_NUM, SUBSCRIBE_NUM, &self.get_ref().upcall) }
+    }
+
+    pub fn unsubscribe(self: Pin<&Self>) {
+        unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+    }
+
+    /// The kernel only accesses the upcall through a shared reference, so this
+    /// does not need to unsubscribe.
+    pub fn upcall(self: Pin<&Self>) -> &U {
+        &self.get_ref().upcall
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    AllowSubscription<DRIVER_NUM, SUBSCRIBE_NUM> for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    type Upcall = U;
+
+    fn subscribe(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        self.as_ref().subscribe()
+    }
+
+    fn unsubscribe(self: Pin<&mut Self>) {
+        self.as_ref().unsubscribe();
+    }
+
+    fn upcall(self: Pin<&Self>) -> &U {
+        Subscription::upcall(self)
+    }
+}
+
+/// The `no_dynamic` implementation, for code that is generic over
+/// `Implementation`.
+pub enum NoDynamicImpl {}
+
+impl Implementation for NoDynamicImpl {
+    type Buffer<S: AllowSlot, B: FromBytes + IntoBytes> = Buffer<S, B>;
+    type Subscription<U: Default + Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> =
+        Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>;
+}
+
+pub(crate) unsafe fn allow_inner<S: AllowSlot>(
     buffer: *const [u8],
-) -> Result<(), ErrorCode> {
-    let (variant, r1, _, _) =
-        unsafe { static_allow::<P>(driver_num, buffer_num, buffer as *mut _, buffer.len()) };
-    if variant == 2 {
-        return Err(r1.addr() as u32);
+) -> Result<ReturnedBuffer, ErrorCode> {
+    let registers =
+        unsafe { static_allow::<S::Class>(S::Driver::NUM, S::NUM, buffer as *mut _, buffer.len()) };
+    decode_allow(registers).map_err(|(error, _)| error)
+}
+
+/// Unshares the allow ID if the Buffer at `owner` may be holding it. Without
+/// the `registry` feature, that is always assumed.
+pub(crate) fn unshare_if_holder<S: AllowSlot>(owner: *const ()) {
+    #[cfg(feature = "registry")]
+    if !registry::take_if_owner(S::Driver::NUM, S::NUM, S::Class::CLASS, owner) {
+        return;
     }
-    Ok(())
+    #[cfg(not(feature = "registry"))]
+    let _ = owner;
+    unshare::<S>();
 }
 
-/// Performs an "unallow" call -- unshares the given buffer with the kernel.
-/// Postcondition: no buffer will be shared with the kernel with ID
-/// (driver_num, buffer_num).
-/// No error handling is needed because if (driver_num, buffer_num) is not
-/// valid, then the buffer could not have been shared in the first place.
-fn unshare<P: StaticType>(driver_num: u32, buffer_num: u32) {
-    unsafe {
-        static_allow::<P>(driver_num, buffer_num, null_mut(), 0);
+/// `unshare_if_holder`, returning the buffer the kernel returned. It is a
+/// separate function because returning the buffer from every `unallow` costs
+/// code size in each monomorphization.
+pub(crate) fn reclaim_if_holder<S: AllowSlot>(owner: *const ()) -> Option<ReturnedBuffer> {
+    #[cfg(feature = "registry")]
+    if !registry::take_if_owner(S::Driver::NUM, S::NUM, S::Class::CLASS, owner) {
+        return None;
     }
+    #[cfg(not(feature = "registry"))]
+    let _ = owner;
+    unshare::<S>()
+}
+
+/// Performs an "unallow" call -- unshares the given buffer with the kernel.
+/// Postcondition: no buffer will be shared with the kernel with allow slot `S`.
+/// No error handling is needed because if `S` is not valid, then the buffer
+/// could not have been shared in the first place.
+/// Returns the buffer that was shared, if the call succeeded.
+fn unshare<S: AllowSlot>() -> Option<ReturnedBuffer> {
+    decode_allow(unsafe { static_allow::<S::Class>(S::Driver::NUM, S::NUM, null_mut(), 0) }).ok()
 }
@@ -0,0 +1,141 @@
+//! A registry of which `no_dynamic::Buffer` currently holds each allow ID,
+//! enabled by the `registry` cargo feature. `no_dynamic` Buffers do not track
+//! whether they are shared, so without the registry they unallow whenever they
+//! might be shared. With the registry, a Buffer only unallows if it is the
+//! current holder of its allow ID.
+//!
+//! The registry is a small fixed-size table of (allow ID, holder address)
+//! entries rather than a bitmap: a bit per allow ID would say that the ID is
+//! shared, but not which Buffer shares it, and a Buffer that is not the holder
+//! must not unallow. The table takes 8 * 16 + 4 = 132 bytes of `.bss` on 32-bit
+//! targets. If more allow IDs are shared at once than it can hold, it stops
+//! tracking for the rest of the program and every Buffer falls back to
+//! unconditionally unallowing, which is always correct.
+
+use crate::DynamicType;
+use core::ptr::null;
+
+/// The number of allow IDs that can be tracked at once.
+const LEN: usize = 8;
+
+// The fields are all zero in an unused entry, so the registry is placed in
+// `.bss` instead of taking flash for its initial value.
+#[derive(Clone, Copy)]
+struct Entry {
+    driver_num: u32,
+    buffer_num: u32,
+    allow_type: u8,
+    // Address of the Buffer holding the allow ID, or null if the entry is
+    // unused. Buffers are pinned, so this is stable until the Buffer is
+    // dropped.
+    owner: *const (),
+}
+
+impl Entry {
+    const UNUSED: Entry = Entry {
+        driver_num: 0,
+        buffer_num: 0,
+        allow_type: 0,
+        owner: null(),
+    };
+}
+
+struct Registry {
+    entries: [Entry; LEN],
+    overflowed: bool,
+}
+
+impl Registry {
+    const fn new() -> Registry {
+        Registry {
+            entries: [Entry::UNUSED; LEN],
+            overflowed: false,
+        }
+    }
+
+    fn find(&mut self, driver_num: u32, buffer_num: u32, allow_type: DynamicType) -> Option<usize> {
+        self.entries.iter().position(|entry| {
+            !entry.owner.is_null()
+                && entry.driver_num == driver_num
+                && entry.buffer_num == buffer_num
+                && entry.allow_type == allow_type as u8
+        })
+    }
+}
+
+// Tock processes are single-threaded and the registry is never borrowed across
+// a system call, so upcalls cannot observe it mid-update either.
+#[cfg(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64"))]
+fn with_registry<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    struct Global(core::cell::UnsafeCell<Registry>);
+    unsafe impl Sync for Global {}
+    static REGISTRY: Global = Global(core::cell::UnsafeCell::new(Registry::new()));
+    f(unsafe { &mut *REGISTRY.0.get() })
+}
+
+// On the host, the registry is thread-local like the fake kernel.
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+std::thread_local! {
+    static REGISTRY: core::cell::RefCell<Registry> = const { core::cell::RefCell::new(Registry::new()) };
+}
+
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+fn with_registry<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    REGISTRY.with_borrow_mut(f)
+}
+
+/// Clears the registry. Called by `fake_kernel::reset`.
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+pub(crate) fn reset() {
+    with_registry(|registry| *registry = Registry::new());
+}
+
+/// Records `owner` as the holder of the allow ID, after it has been allowed.
+pub(crate) fn set_owner(
+    driver_num: u32,
+    buffer_num: u32,
+    allow_type: DynamicType,
+    owner: *const (),
+) {
+    with_registry(|registry| {
+        let entry = Entry {
+            driver_num,
+            buffer_num,
+            allow_type: allow_type as u8,
+            owner,
+        };
+        match registry.find(driver_num, buffer_num, allow_type) {
+            Some(i) => registry.entries[i] = entry,
+            None => match registry
+                .entries
+                .iter()
+                .position(|entry| entry.owner.is_null())
+            {
+                Some(i) => registry.entries[i] = entry,
+                None => registry.overflowed = true,
+            },
+        }
+    })
+}
+
+/// Returns true if `owner` may hold the allow ID, in which case the caller must
+/// unallow it, and forgets the holder.
+pub(crate) fn take_if_owner(
+    driver_num: u32,
+    buffer_num: u32,
+    allow_type: DynamicType,
+    owner: *const (),
+) -> bool {
+    with_registry(
+        |registry| match registry.find(driver_num, buffer_num, allow_type) {
+            Some(i) if registry.entries[i].owner == owner => {
+                registry.entries[i] = Entry::UNUSED;
+                true
+            }
+            Some(_) => false,
+            // Not held by anyone, unless the registry overflowed and could not
+            // record the holder.
+            None => registry.overflowed,
+        },
+    )
+}
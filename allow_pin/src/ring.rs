@@ -0,0 +1,107 @@
+//! A ring of `N` `no_dynamic` buffers that take turns being shared with one
+//! allow ID, for streams that arrive faster than the app can process each
+//! buffer. `rotate` shares the next buffer and queues the one the kernel was
+//! writing as filled; the app consumes filled buffers in order with `consume`,
+//! which frees them to be shared again. The ring only hands out filled buffers,
+//! so a buffer is never accessible while it is shared.
+
+use crate::no_dynamic::Buffer;
+use crate::*;
+
+/// `N` buffers that are shared with allow slot `S` in rotation.
+pub struct BufferRing<S: AllowSlot, B: FromBytes + IntoBytes, const N: usize> {
+    // Every buffer is a structurally pinned field.
+    buffers: [Buffer<S, B>; N],
+    // The index of the shared buffer, if the ring has been started.
+    shared: Option<usize>,
+    // The number of filled buffers, which are the ones before `shared`.
+    filled: usize,
+}
+
+impl<S: AllowSlot, B: Default + FromBytes + IntoBytes, const N: usize> Default
+    for BufferRing<S, B, N>
+{
+    fn default() -> BufferRing<S, B, N> {
+        Self::from(core::array::from_fn(|_| B::default()))
+    }
+}
+
+impl<S: AllowSlot, B: FromBytes + IntoBytes, const N: usize> From<[B; N]> for BufferRing<S, B, N> {
+    fn from(buffers: [B; N]) -> BufferRing<S, B, N> {
+        const { assert!(N >= 2, "a ring needs at least two buffers") };
+        BufferRing {
+            buffers: buffers.map(Buffer::from),
+            shared: None,
+            filled: 0,
+        }
+    }
+}
+
+impl<S: AllowSlot, B: FromBytes + IntoBytes, const N: usize> BufferRing<S, B, N> {
+    fn buffer(&mut self, index: usize) -> Pin<&mut Buffer<S, B>> {
+        unsafe { Pin::new_unchecked(&mut self.buffers[index]) }
+    }
+
+    /// Shares the first buffer, starting the stream.
+    pub fn start(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.shared.is_some() {
+            return Err(ErrorCode::Already);
+        }
+        this.buffer(0).allow()?;
+        this.shared = Some(0);
+        Ok(())
+    }
+
+    /// Shares the next buffer in the ring, which takes the current buffer back
+    /// from the kernel in the same Allow call, and queues the current buffer as
+    /// filled. Fails with `Busy` if the next buffer is still filled, and with
+    /// `Off` if the ring was not started. If sharing the next buffer fails, the
+    /// current buffer is shared again, so it keeps its data and stays current.
+    pub fn rotate(self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let Some(current) = this.shared else {
+            return Err(ErrorCode::Off);
+        };
+        if this.filled == N - 1 {
+            return Err(ErrorCode::Busy);
+        }
+        let next = (current + 1) % N;
+        let [mut old, new] = this
+            .buffers
+            .get_disjoint_mut([current, next])
+            .unwrap()
+            .map(|buffer| unsafe { Pin::new_unchecked(buffer) });
+        let (_, result) = old.as_mut().replace_with_mut(new);
+        if let Err(error) = result {
+            // If this fails too, nothing is shared, but the current buffer
+            // keeps its data and the next rotate tries to swap it out again.
+            let _ = old.allow();
+            return Err(error);
+        }
+        this.shared = Some(next);
+        this.filled += 1;
+        Ok(())
+    }
+
+    /// The number of filled buffers waiting to be consumed.
+    pub fn filled(self: Pin<&Self>) -> usize {
+        self.filled
+    }
+
+    /// Calls `f` with the oldest filled buffer, then frees that buffer to be
+    /// shared again. Returns `None` if no buffer is filled.
+    pub fn consume<R>(self: Pin<&mut Self>, f: impl FnOnce(&mut B) -> R) -> Option<R> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let current = this.shared?;
+        if this.filled == 0 {
+            return None;
+        }
+        let oldest = (current + N - this.filled) % N;
+        // The oldest filled buffer is not shared, so borrowing it does not need
+        // to unallow (which would take back the shared buffer instead).
+        let result = f(this.buffer(oldest).buffer_mut_unshared());
+        this.filled -= 1;
+        Some(result)
+    }
+}
@@ -0,0 +1,181 @@
+//! A closure-scoped implementation that does not use `Pin`. `scope` calls a
+//! closure with a `Handle` for a list of allow and subscribe IDs, and every ID
+//! in the list is unallowed (or unsubscribed) when the closure returns. Buffers
+//! and upcalls shared through the handle only need to outlive the `scope` call
+//! (`'scope`), so they can be ordinary locals. Nothing tracks which IDs were
+//! actually used, so every ID in the list is cleared when the scope ends.
+//!
+//! ```compile_fail
+//! # use allow_pin::share::*;
+//! scope::<allow_pin::console::WriteBuffer, _, _>(|handle| {
+//!     let buffer = [0u8; 4];
+//!     handle.allow(&buffer) // Error: `buffer` does not outlive the scope.
+//! });
+//! ```
+//!
+//! Because a buffer stays borrowed until the scope ends, one buffer cannot be
+//! read while another is shared with the same ID (as the swap examples do with
+//! the `Pin`-based implementations); the buffers must be shared in separate
+//! scopes.
+
+use crate::*;
+use core::ptr;
+
+pub use crate::{AllowRo, AllowRw, AllowSlot, Driver};
+
+/// A subscribe ID.
+pub struct Subscribe<const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>;
+
+/// A list of IDs a `scope` can share. Implemented for the ID types and for
+/// tuples of them.
+pub trait List {
+    /// Clears every ID in the list.
+    fn unshare_all();
+}
+
+impl<D: Driver, const NUM: u32> List for AllowRo<D, NUM> {
+    fn unshare_all() {
+        unshare::<Self>();
+    }
+}
+
+impl<D: Driver, const NUM: u32> List for AllowRw<D, NUM> {
+    fn unshare_all() {
+        unshare::<Self>();
+    }
+}
+
+impl<const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> List
+    for Subscribe<DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn unshare_all() {
+        unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+    }
+}
+
+/// Proof that the caller is inside a `scope` that will clear the IDs in `L`
+/// before `'scope` ends. `'scope` is invariant so it cannot be shortened to fit
+/// a buffer that does not live long enough. Handles are only ever handed out
+/// by reference, with a lifetime that is local to the `scope` closure, so they
+/// cannot escape the scope.
+pub struct Handle<'scope, L: List> {
+    _list: PhantomData<L>,
+    _scope: PhantomData<Cell<&'scope ()>>,
+}
+
+impl<'scope, L: List> Handle<'scope, L> {
+    // Handles are zero-sized, so a dangling reference is valid.
+    fn new<'h>() -> &'h Handle<'scope, L> {
+        unsafe { ptr::NonNull::dangling().as_ref() }
+    }
+}
+
+/// Calls `f` with a handle for the IDs in `L`, then clears those IDs. The IDs
+/// are also cleared if `f` panics and unwinds. As a lifetime parameter of this
+/// function, `'scope` outlives the call, so anything shared for `'scope` stays
+/// valid until the IDs are cleared.
+pub fn scope<'scope, L: List, R, F: for<'h> FnOnce(&'h Handle<'scope, L>) -> R>(f: F) -> R {
+    struct UnshareOnDrop<L: List>(PhantomData<L>);
+    impl<L: List> Drop for UnshareOnDrop<L> {
+        fn drop(&mut self) {
+            L::unshare_all();
+        }
+    }
+
+    let _unshare = UnshareOnDrop::<L>(PhantomData);
+    f(Handle::new())
+}
+
+// Implements List and Handle::split for a tuple of IDs.
+macro_rules! tuple_list {
+    ($($name:ident),*) => {
+        impl<$($name: List),*> List for ($($name,)*) {
+            fn unshare_all() {
+                $($name::unshare_all();)*
+            }
+        }
+
+        impl<'scope, $($name: List),*> Handle<'scope, ($($name,)*)> {
+            /// Splits this handle into a handle for each ID in the list.
+            pub fn split(&self) -> ($(&Handle<'scope, $name>,)*) {
+                ($(Handle::<'scope, $name>::new(),)*)
+            }
+        }
+    };
+}
+
+tuple_list!(A, B);
+tuple_list!(A, B, C);
+tuple_list!(A, B, C, D);
+
+impl<'scope, D: Driver, const NUM: u32> Handle<'scope, AllowRo<D, NUM>> {
+    /// Shares `buffer` read-only until the end of the scope, replacing any
+    /// buffer that was previously shared with this ID.
+    pub fn allow<B: FromBytes + IntoBytes + ?Sized>(
+        &self,
+        buffer: &'scope B,
+    ) -> Result<(), ErrorCode> {
+        unsafe {
+            allow_inner::<AllowRo<D, NUM>>(ptr::slice_from_raw_parts(
+                ptr::from_ref(buffer).cast(),
+                size_of_val(buffer),
+            ))
+        }?;
+        Ok(())
+    }
+
+    /// Unallows this ID before the end of the scope.
+    pub fn unallow(&self) {
+        unshare::<AllowRo<D, NUM>>();
+    }
+}
+
+impl<'scope, D: Driver, const NUM: u32> Handle<'scope, AllowRw<D, NUM>> {
+    /// Shares `buffer` read-write until the end of the scope, replacing any
+    /// buffer that was previously shared with this ID.
+    pub fn allow<B: FromBytes + IntoBytes + ?Sized>(
+        &self,
+        buffer: &'scope mut B,
+    ) -> Result<(), ErrorCode> {
+        unsafe {
+            allow_inner::<AllowRw<D, NUM>>(ptr::slice_from_raw_parts(
+                ptr::from_mut(buffer).cast(),
+                size_of_val(buffer),
+            ))
+        }?;
+        Ok(())
+    }
+
+    /// Unallows this ID before the end of the scope. `buffer` remains borrowed
+    /// until the end of the scope.
+    pub fn unallow(&self) {
+        unshare::<AllowRw<D, NUM>>();
+    }
+}
+
+impl<'scope, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    Handle<'scope, Subscribe<DRIVER_NUM, SUBSCRIBE_NUM>>
+{
+    /// Registers `upcall` until the end of the scope.
+    pub fn subscribe<U: Upcall>(&self, upcall: &'scope U) -> Result<(), ErrorCode> {
+        unsafe { subscribe_inner(DRIVER_NUM, SUBSCRIBE_NUM, upcall) }
+    }
+
+    /// Unsubscribes before the end of the scope.
+    pub fn unsubscribe(&self) {
+        unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+    }
+}
+
+unsafe fn allow_inner<S: AllowSlot>(buffer: *const [u8]) -> Result<ReturnedBuffer, ErrorCode> {
+    let registers =
+        unsafe { static_allow::<S::Class>(S::Driver::NUM, S::NUM, buffer as *mut _, buffer.len()) };
+    decode_allow(registers).map_err(|(error, _)| error)
+}
+
+/// Performs an "unallow" call. No error handling is needed because if `S` is
+/// not valid, then the buffer could not have been shared in the first place.
+/// Returns the buffer that was shared, if the call succeeded.
+fn unshare<S: AllowSlot>() -> Option<ReturnedBuffer> {
+    decode_allow(unsafe { static_allow::<S::Class>(S::Driver::NUM, S::NUM, null_mut(), 0) }).ok()
+}
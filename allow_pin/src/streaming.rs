@@ -0,0 +1,151 @@
+//! A receiver for Tock streaming process slices, built on `no_dynamic`. The
+//! kernel appends data to a read-write buffer after an 8-byte header:
+//!
+//! ```text
+//! 0         2         4                   8
+//! +---------+---------+-------------------+------...
+//! | version | flags   | write offset      | data
+//! +---------+---------+-------------------+------...
+//! ```
+//!
+//! All header fields are native-endian. The write offset is the number of data
+//! bytes the kernel has written, and bit 0 of flags is set if the kernel had
+//! more data than fit in the buffer. The app receives data by swapping in a
+//! second buffer with a zeroed header, which atomically takes the first buffer
+//! back from the kernel.
+
+use crate::no_dynamic::Buffer;
+use crate::*;
+
+/// The length of the streaming process slice header.
+pub const HEADER_LEN: usize = 8;
+
+/// The only header version this receiver understands.
+pub const VERSION: u16 = 0;
+
+/// Flag set by the kernel when it could not fit all of its data in the buffer.
+const EXCEEDED: u16 = 1 << 0;
+
+/// A streaming process slice header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub version: u16,
+    pub flags: u16,
+    pub write_offset: u32,
+}
+
+impl Header {
+    pub fn read(bytes: &[u8; HEADER_LEN]) -> Header {
+        Header {
+            version: u16::from_ne_bytes([bytes[0], bytes[1]]),
+            flags: u16::from_ne_bytes([bytes[2], bytes[3]]),
+            write_offset: u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    /// True if the kernel dropped data because the buffer was full.
+    pub fn exceeded(&self) -> bool {
+        self.flags & EXCEEDED != 0
+    }
+}
+
+/// The data the kernel wrote into a buffer before it was swapped out.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Received<'b> {
+    pub data: &'b mut [u8],
+    /// The kernel dropped data after `data` because the buffer was full.
+    pub exceeded: bool,
+}
+
+/// Parses a buffer that has been swapped out, returning the data the kernel
+/// wrote, and zeroes its header so it can be shared again. Fails with
+/// `NoSupport` for an unknown header version and `Size` if the write offset is
+/// past the end of the buffer.
+pub fn parse(buffer: &mut [u8]) -> Result<Received<'_>, ErrorCode> {
+    let Some((header, data)) = buffer.split_first_chunk_mut::<HEADER_LEN>() else {
+        return Err(ErrorCode::Size);
+    };
+    let parsed = Header::read(header);
+    *header = [0; HEADER_LEN];
+    if parsed.version != VERSION {
+        return Err(ErrorCode::NoSupport);
+    }
+    let Some(data) = data.get_mut(..parsed.write_offset as usize) else {
+        return Err(ErrorCode::Size);
+    };
+    Ok(Received {
+        data,
+        exceeded: parsed.exceeded(),
+    })
+}
+
+/// Receives a stream from allow slot `S` into two buffers of `LEN` bytes
+/// (including the header), one of which is shared at a time.
+pub struct StreamingReceiveSlice<S: AllowSlot, const LEN: usize> {
+    // Both buffer_a and buffer_b are structurally pinned fields.
+    buffer_a: Buffer<S, [u8; LEN]>,
+    buffer_b: Buffer<S, [u8; LEN]>,
+    share_status: ShareStatus,
+}
+
+// Which buffer is currently shared.
+#[derive(Clone, Copy)]
+enum ShareStatus {
+    None,
+    A,
+    B,
+}
+
+impl<S: AllowSlot, const LEN: usize> Default for StreamingReceiveSlice<S, LEN> {
+    fn default() -> StreamingReceiveSlice<S, LEN> {
+        const { assert!(LEN >= HEADER_LEN, "buffers must fit the header") };
+        // The buffers start out zeroed, which is an empty header.
+        StreamingReceiveSlice {
+            buffer_a: Buffer::from([0; LEN]),
+            buffer_b: Buffer::from([0; LEN]),
+            share_status: ShareStatus::None,
+        }
+    }
+}
+
+impl<S: AllowSlot, const LEN: usize> StreamingReceiveSlice<S, LEN> {
+    /// Shares the first buffer, starting the receive.
+    pub fn start(mut self: Pin<&mut Self>) -> Result<(), ErrorCode> {
+        if !matches!(self.share_status, ShareStatus::None) {
+            return Err(ErrorCode::Already);
+        }
+        unsafe { self.as_mut().map_unchecked_mut(|s| &mut s.buffer_a) }.allow()?;
+        unsafe { self.get_unchecked_mut() }.share_status = ShareStatus::A;
+        Ok(())
+    }
+
+    /// Swaps the buffers, sharing the other buffer in the same Allow call that
+    /// takes back the current one, and returns what the kernel wrote into the
+    /// current buffer. Fails with `Off` if the receive was not started.
+    ///
+    /// If sharing the other buffer fails, the current buffer is shared again,
+    /// so the kernel keeps appending to it and the next call returns its data.
+    pub fn next(self: Pin<&mut Self>) -> Result<Received<'_>, ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let [a, b] = unsafe {
+            [
+                Pin::new_unchecked(&mut this.buffer_a),
+                Pin::new_unchecked(&mut this.buffer_b),
+            ]
+        };
+        let (mut old, new, status) = match this.share_status {
+            ShareStatus::None => return Err(ErrorCode::Off),
+            ShareStatus::A => (a, b, ShareStatus::B),
+            ShareStatus::B => (b, a, ShareStatus::A),
+        };
+        let (_, result) = old.as_mut().replace_with_mut(new);
+        if let Err(error) = result {
+            // If this fails too, nothing is shared, but the current buffer
+            // keeps its data and the next call tries to swap it out again.
+            let _ = old.allow();
+            return Err(error);
+        }
+        this.share_status = status;
+        parse(old.buffer_mut_unshared())
+    }
+}
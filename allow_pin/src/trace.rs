@@ -0,0 +1,167 @@
+//! A trace of every Allow and Command system call, enabled by the `trace`
+//! cargo feature, for counting the duplicate and unnecessary unallows an
+//! implementation makes on a real workload without reading its disassembly.
+//!
+//! The trace is a fixed-size ring of the most recent `LEN` calls, so it can be
+//! left running: once it is full, each call overwrites the oldest entry. `dump`
+//! reads it back, oldest first.
+
+use crate::DynamicType;
+use core::mem::MaybeUninit;
+use core::ptr::null_mut;
+
+/// The number of calls the trace holds.
+pub const LEN: usize = 64;
+
+/// Which system call an entry records.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// An Allow with an allow type that was known at compile time.
+    StaticAllow,
+    /// An Allow with an allow type chosen at runtime.
+    DynamicAllow,
+    /// An Allow of the empty buffer, through either path.
+    Unshare,
+    Command,
+}
+
+/// One traced system call.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub driver_num: u32,
+    /// The allow number, or the command number of a `Command`.
+    pub buffer_num: u32,
+    /// The allow type, or `None` for a `Command`.
+    pub class: Option<DynamicType>,
+    pub address: *mut u8,
+    pub len: usize,
+}
+
+struct Trace {
+    // Only the `recorded.min(LEN)` entries before `recorded % LEN` (wrapping
+    // around) are initialized. Leaving the rest uninitialized keeps the trace
+    // in .bss rather than .data.
+    entries: [MaybeUninit<Entry>; LEN],
+    // The number of calls recorded since the trace was last cleared.
+    recorded: usize,
+}
+
+impl Trace {
+    const fn new() -> Trace {
+        Trace {
+            entries: [MaybeUninit::uninit(); LEN],
+            recorded: 0,
+        }
+    }
+}
+
+// Tock processes are single-threaded and the trace is never borrowed across a
+// system call, so upcalls cannot observe it mid-update either.
+#[cfg(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64"))]
+fn with_trace<R>(f: impl FnOnce(&mut Trace) -> R) -> R {
+    struct Global(core::cell::UnsafeCell<Trace>);
+    unsafe impl Sync for Global {}
+    static TRACE: Global = Global(core::cell::UnsafeCell::new(Trace::new()));
+    f(unsafe { &mut *TRACE.0.get() })
+}
+
+// On the host, the trace is thread-local like the fake kernel.
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+std::thread_local! {
+    static TRACE: core::cell::RefCell<Trace> = const { core::cell::RefCell::new(Trace::new()) };
+}
+
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+fn with_trace<R>(f: impl FnOnce(&mut Trace) -> R) -> R {
+    TRACE.with_borrow_mut(f)
+}
+
+fn record(entry: Entry) {
+    with_trace(|trace| {
+        trace.entries[trace.recorded % LEN].write(entry);
+        trace.recorded += 1;
+    })
+}
+
+/// Records an Allow call. `kind` is `StaticAllow` or `DynamicAllow`, and is
+/// replaced by `Unshare` if the call shares no buffer.
+pub(crate) fn record_allow(
+    kind: Kind,
+    driver_num: u32,
+    buffer_num: u32,
+    class: DynamicType,
+    address: *mut u8,
+    len: usize,
+) {
+    record(Entry {
+        kind: match address.is_null() && len == 0 {
+            true => Kind::Unshare,
+            false => kind,
+        },
+        driver_num,
+        buffer_num,
+        class: Some(class),
+        address,
+        len,
+    });
+}
+
+pub(crate) fn record_command(driver_num: u32, command_num: u32) {
+    record(Entry {
+        kind: Kind::Command,
+        driver_num,
+        buffer_num: command_num,
+        class: None,
+        address: null_mut(),
+        len: 0,
+    });
+}
+
+/// Calls `f` with every entry in the trace, oldest first, and returns the
+/// number of entries that were overwritten since the trace was last cleared.
+/// The trace is left unchanged.
+pub fn dump(mut f: impl FnMut(&Entry)) -> usize {
+    // Copy the entries out so `f` may make system calls, which are traced.
+    let (entries, recorded) = with_trace(|trace| (trace.entries, trace.recorded));
+    let len = recorded.min(LEN);
+    for i in recorded - len..recorded {
+        f(unsafe { entries[i % LEN].assume_init_ref() });
+    }
+    recorded - len
+}
+
+/// Empties the trace.
+pub fn clear() {
+    with_trace(|trace| *trace = Trace::new());
+}
+
+/// Counts the redundant unshares in `entries` (in the order `dump` passes
+/// them): those of an allow ID whose previous traced Allow was also an
+/// unshare. The first unshare of each allow ID is not counted, as the trace
+/// may not go back far enough to show whether it was shared. Only `LEN`
+/// unshared allow IDs are tracked at once, which is all a dump can contain.
+pub fn redundant_unshares<'e>(entries: impl IntoIterator<Item = &'e Entry>) -> usize {
+    // The allow IDs whose most recent entry so far is an unshare, which is
+    // never more than the number of entries in the trace.
+    let mut unshared: [Option<(u32, u32, Option<DynamicType>)>; LEN] = [None; LEN];
+    let mut redundant = 0;
+    for entry in entries {
+        if entry.kind == Kind::Command {
+            continue;
+        }
+        let id = Some((entry.driver_num, entry.buffer_num, entry.class));
+        let position = unshared.iter().position(|&slot| slot == id);
+        match (entry.kind, position) {
+            (Kind::Unshare, Some(_)) => redundant += 1,
+            (Kind::Unshare, None) => {
+                if let Some(slot) = unshared.iter_mut().find(|slot| slot.is_none()) {
+                    *slot = id;
+                }
+            }
+            (_, Some(i)) => unshared[i] = None,
+            (_, None) => {}
+        }
+    }
+    redundant
+}
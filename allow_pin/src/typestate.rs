@@ -0,0 +1,291 @@
+//! An implementation that tracks whether a buffer is shared in the type system
+//! rather than at runtime. A pinned `Buffer` is accessed through an `Unshared`
+//! handle. Sharing the handle consumes it and returns a `Shared` guard
+//! (`SharedRo` or `SharedRw`), so reading the buffer while the kernel holds it
+//! is a compile error. Unsharing the guard gives the handle back.
+//!
+//! ```compile_fail
+//! # use allow_pin::typestate::*;
+//! # use core::pin::pin;
+//! let mut buffer = pin!(Buffer::<allow_pin::rng::Buffer, [u8; 4]>::from([0; 4]));
+//! let handle = buffer.as_mut().handle();
+//! let shared = handle.share().map_err(|(_, error)| error).unwrap();
+//! let _ = handle.buffer(); // Error: `handle` was consumed by `share`.
+//! ```
+//!
+//! Leaking a guard (e.g. with `mem::forget`) ends its borrow of the `Buffer`
+//! without unallowing. To keep that sound without a runtime flag, creating a
+//! handle and dropping a `Buffer` both unconditionally unallow. As a result,
+//! handles should be created before any buffer is shared with the same ID.
+
+use crate::*;
+use core::mem::ManuallyDrop;
+use core::ptr;
+
+pub use crate::{AllowRo, AllowRw, AllowSlot, AllowUserspaceReadable, Driver};
+
+/// A buffer that can be shared with allow slot `S`.
+pub struct Buffer<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> {
+    _slot: PhantomData<S>,
+    _pinned: PhantomPinned,
+    buffer: B,
+}
+
+impl<S: AllowSlot, B: Default + FromBytes + IntoBytes> Default for Buffer<S, B> {
+    fn default() -> Buffer<S, B> {
+        Buffer {
+            _slot: PhantomData,
+            _pinned: PhantomPinned,
+            buffer: Default::default(),
+        }
+    }
+}
+
+impl<S: AllowSlot, B: FromBytes + IntoBytes> From<B> for Buffer<S, B> {
+    fn from(buffer: B) -> Buffer<S, B> {
+        Buffer {
+            _slot: PhantomData,
+            _pinned: PhantomPinned,
+            buffer,
+        }
+    }
+}
+
+// The guard that shared this buffer may have been leaked, so this must
+// unconditionally unallow.
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> Drop for Buffer<S, B> {
+    fn drop(&mut self) {
+        unshare::<S>();
+    }
+}
+
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> Buffer<S, B> {
+    /// Returns a handle to this buffer. Unallows this buffer's ID, as a guard
+    /// that previously shared this buffer may have been leaked.
+    pub fn handle(self: Pin<&mut Self>) -> Unshared<'_, S, B> {
+        unshare::<S>();
+        Unshared { buffer: self }
+    }
+}
+
+/// A handle to a `Buffer` that is not shared with the kernel.
+pub struct Unshared<'a, S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> {
+    buffer: Pin<&'a mut Buffer<S, B>>,
+}
+
+impl<'a, S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> Unshared<'a, S, B> {
+    /// Shares the buffer with the kernel. On failure, returns the handle
+    /// alongside the error.
+    #[allow(clippy::type_complexity)]
+    pub fn share(mut self) -> Result<Shared<'a, S, B>, (Self, ErrorCode)> {
+        let this = unsafe { self.buffer.as_mut().get_unchecked_mut() };
+        let buffer =
+            ptr::slice_from_raw_parts_mut((&raw mut this.buffer).cast(), size_of_val(&this.buffer));
+        match unsafe { allow_inner::<S>(buffer) } {
+            Ok(_) => Ok(Shared {
+                buffer: ManuallyDrop::new(self.buffer),
+            }),
+            Err(error) => Err((self, error)),
+        }
+    }
+
+    pub fn buffer(&self) -> &B {
+        &self.buffer.as_ref().get_ref().buffer
+    }
+
+    pub fn buffer_mut(&mut self) -> &mut B {
+        &mut unsafe { self.buffer.as_mut().get_unchecked_mut() }.buffer
+    }
+
+    /// Converts this handle into a mutable reference to the buffer, for
+    /// callers that are done with the handle.
+    pub fn into_buffer_mut(self) -> &'a mut B {
+        &mut unsafe { Pin::into_inner_unchecked(self.buffer) }.buffer
+    }
+}
+
+/// A guard representing a `Buffer` that is shared with the kernel. Unallows the
+/// buffer when dropped.
+pub struct Shared<'a, S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> {
+    buffer: ManuallyDrop<Pin<&'a mut Buffer<S, B>>>,
+}
+
+pub type SharedRo<'a, D, const NUM: u32, B> = Shared<'a, AllowRo<D, NUM>, B>;
+pub type SharedRw<'a, D, const NUM: u32, B> = Shared<'a, AllowRw<D, NUM>, B>;
+
+impl<S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> Drop for Shared<'_, S, B> {
+    fn drop(&mut self) {
+        unshare::<S>();
+    }
+}
+
+impl<'a, S: AllowSlot, B: FromBytes + IntoBytes + ?Sized> Shared<'a, S, B> {
+    /// Unallows the buffer, returning its handle.
+    pub fn unshare(self) -> Unshared<'a, S, B> {
+        unshare::<S>();
+        Unshared {
+            buffer: self.into_inner(),
+        }
+    }
+
+    /// Shares `new` in place of this buffer, returning this buffer's handle
+    /// without a separate unallow (sharing `new` replaces it). On failure, this
+    /// buffer remains shared and both are returned alongside the error.
+    #[allow(clippy::type_complexity)]
+    pub fn replace_with<'b, OB: FromBytes + IntoBytes + ?Sized>(
+        self,
+        new: Unshared<'b, S, OB>,
+    ) -> Result<(Unshared<'a, S, B>, Shared<'b, S, OB>), (Self, Unshared<'b, S, OB>, ErrorCode)>
+    {
+        match new.share() {
+            Ok(new) => Ok((
+                Unshared {
+                    buffer: self.into_inner(),
+                },
+                new,
+            )),
+            Err((new, error)) => Err((self, new, error)),
+        }
+    }
+
+    /// Takes the buffer reference out of the guard without unallowing.
+    fn into_inner(self) -> Pin<&'a mut Buffer<S, B>> {
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.buffer) }
+    }
+}
+
+// The kernel cannot write to a read-only buffer, so it can be read while
+// shared.
+impl<D: Driver, const NUM: u32, B: FromBytes + IntoBytes + ?Sized> Shared<'_, AllowRo<D, NUM>, B> {
+    pub fn buffer(&self) -> &B {
+        &self.buffer.as_ref().get_ref().buffer
+    }
+}
+
+// The kernel may write to a userspace-readable buffer while it is shared, so
+// it is only read through volatile reads.
+impl<D: Driver, const NUM: u32, B: FromBytes + IntoBytes + ?Sized>
+    Shared<'_, AllowUserspaceReadable<D, NUM>, B>
+{
+    /// Copies `dest.len()` bytes starting at `offset` into `dest`. The copy may
+    /// be torn by a concurrent kernel write.
+    pub fn read_volatile(&mut self, offset: usize, dest: &mut [u8]) {
+        let this = unsafe { self.buffer.as_mut().get_unchecked_mut() };
+        assert!(
+            offset
+                .checked_add(dest.len())
+                .is_some_and(|end| end <= size_of_val(&this.buffer))
+        );
+        unsafe { read_volatile_bytes((&raw const this.buffer).cast::<u8>().add(offset), dest) }
+    }
+}
+
+impl<D: Driver, const NUM: u32, B: FromBytes + IntoBytes + Immutable>
+    Shared<'_, AllowUserspaceReadable<D, NUM>, B>
+{
+    /// Reads the buffer, retrying until the read is not torn by a concurrent
+    /// kernel write. Returns `None` if the kernel kept changing it.
+    pub fn read(&mut self) -> Option<B> {
+        unsafe { read_untorn(&raw const self.buffer.as_mut().get_unchecked_mut().buffer) }
+    }
+}
+
+/// An upcall registration. Subscribing consumes the pinned `Subscription` and
+/// returns a `Subscribed` guard, which unsubscribes when dropped. As with
+/// `Buffer`, the guard may be leaked, so dropping a `Subscription`
+/// unconditionally unsubscribes.
+pub struct Subscription<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> {
+    _pinned: PhantomPinned,
+    upcall: U,
+}
+
+impl<U: Default + Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> Default
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn default() -> Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM> {
+        Subscription {
+            _pinned: PhantomPinned,
+            upcall: Default::default(),
+        }
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> From<U>
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn from(upcall: U) -> Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM> {
+        Subscription {
+            _pinned: PhantomPinned,
+            upcall,
+        }
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> Drop
+    for Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn drop(&mut self) {
+        unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+    }
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    pub fn subscribe(
+        self: Pin<&mut Self>,
+    ) -> Result<Subscribed<'_, U, DRIVER_NUM, SUBSCRIBE_NUM>, ErrorCode> {
+        unsafe { subscribe_inner(DRIVER_NUM, SUBSCRIBE_NUM, &self.upcall) }?;
+        Ok(Subscribed {
+            subscription: ManuallyDrop::new(self),
+        })
+    }
+
+    pub fn upcall(self: Pin<&Self>) -> &U {
+        &self.get_ref().upcall
+    }
+}
+
+/// A guard representing a subscribed `Subscription`.
+pub struct Subscribed<'a, U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> {
+    subscription: ManuallyDrop<Pin<&'a mut Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>>>,
+}
+
+impl<U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32> Drop
+    for Subscribed<'_, U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    fn drop(&mut self) {
+        unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+    }
+}
+
+impl<'a, U: Upcall, const DRIVER_NUM: u32, const SUBSCRIBE_NUM: u32>
+    Subscribed<'a, U, DRIVER_NUM, SUBSCRIBE_NUM>
+{
+    /// Unsubscribes, returning the `Subscription`.
+    pub fn unsubscribe(self) -> Pin<&'a mut Subscription<U, DRIVER_NUM, SUBSCRIBE_NUM>> {
+        unsubscribe(DRIVER_NUM, SUBSCRIBE_NUM);
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.subscription) }
+    }
+
+    /// The kernel only accesses the upcall through a shared reference, so this
+    /// is available while subscribed.
+    pub fn upcall(&self) -> &U {
+        &self.subscription.as_ref().get_ref().upcall
+    }
+}
+
+unsafe fn allow_inner<S: AllowSlot>(buffer: *mut [u8]) -> Result<ReturnedBuffer, ErrorCode> {
+    let registers =
+        unsafe { static_allow::<S::Class>(S::Driver::NUM, S::NUM, buffer as *mut _, buffer.len()) };
+    decode_allow(registers).map_err(|(error, _)| error)
+}
+
+/// Performs an "unallow" call. No error handling is needed because if `S` is
+/// not valid, then the buffer could not have been shared in the first place.
+/// Returns the buffer that was shared, if the call succeeded.
+fn unshare<S: AllowSlot>() -> Option<ReturnedBuffer> {
+    decode_allow(unsafe { static_allow::<S::Class>(S::Driver::NUM, S::NUM, null_mut(), 0) }).ok()
+}
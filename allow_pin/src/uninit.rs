@@ -0,0 +1,170 @@
+//! A read-write Buffer over uninitialized memory, for receive buffers that
+//! would otherwise be zero-filled only for the kernel to overwrite them. The
+//! app cannot read the buffer until it takes it back from the kernel with
+//! `unallow`, which returns only the prefix the kernel reports it wrote
+//! (usually a length argument of the driver's upcall).
+//!
+//! Nothing but the kernel's report says which bytes it wrote: an `asm!` block
+//! that may write memory does not initialize the bytes it leaves alone, so the
+//! rest of the buffer stays uninitialized and is never exposed. The report is
+//! recorded by this module's `Subscription`, whose length only the kernel can
+//! set, so `unallow` takes the Subscription rather than a length and needs no
+//! `unsafe`. On the host the buffer starts filled with `POISON` rather than
+//! uninitialized. That differs from `fake_kernel::SCRIBBLE`, so tests can tell
+//! the bytes the fake kernel wrote from those it left alone.
+//!
+//! Like `no_dynamic`, this does not track whether the buffer is shared, so it
+//! unallows on drop and in `unallow` (with the `registry` feature, only if it
+//! holds the allow ID).
+
+use crate::no_dynamic::{allow_inner, reclaim_if_holder, unshare_if_holder};
+use crate::*;
+use core::cell::Cell;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+/// What the buffer starts filled with on the host, where it stands in for
+/// uninitialized memory. It differs from `fake_kernel::SCRIBBLE`.
+#[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+pub const POISON: u8 = 0x5A;
+
+/// An `N`-byte buffer that can be shared with allow slot `S`, which starts
+/// uninitialized.
+pub struct Buffer<S: AllowSlot, const N: usize> {
+    _slot: PhantomData<S>,
+    _pinned: PhantomPinned,
+    // The Subscription passed to the last successful allow, or null. Only its
+    // report can say how much of the buffer the kernel initialized.
+    subscription: *const (),
+    buffer: [MaybeUninit<u8>; N],
+}
+
+impl<S: AllowSlot, const N: usize> Default for Buffer<S, N> {
+    fn default() -> Buffer<S, N> {
+        #[cfg(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64"))]
+        let buffer = [MaybeUninit::uninit(); N];
+        #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+        let buffer = [MaybeUninit::new(POISON); N];
+        Buffer {
+            _slot: PhantomData,
+            _pinned: PhantomPinned,
+            subscription: ptr::null(),
+            buffer,
+        }
+    }
+}
+
+// Same surprising semantics as `no_dynamic::Buffer`: a Buffer that is never
+// allowed still clears its allow ID on drop (unless the `registry` feature is
+// enabled).
+impl<S: AllowSlot, const N: usize> Drop for Buffer<S, N> {
+    fn drop(&mut self) {
+        unshare_if_holder::<S>(ptr::from_ref(self).cast());
+    }
+}
+
+// Only read-write allow is supported: the kernel never writes an RO buffer, and
+// a userspace-readable buffer may be read while it is shared.
+impl<D: Driver, const NUM: u32, const N: usize> Buffer<AllowRw<D, NUM>, N> {
+    /// Shares the buffer, clearing any length `done` recorded before, so only
+    /// an upcall delivered while this buffer is shared can report its length.
+    pub fn allow<const SUBSCRIBE_NUM: u32, const ARG: usize>(
+        self: Pin<&mut Self>,
+        done: Pin<&Subscription<D, SUBSCRIBE_NUM, ARG>>,
+    ) -> Result<(), ErrorCode> {
+        let this = unsafe { self.get_unchecked_mut() };
+        done.written.0.set(None);
+        unsafe {
+            allow_inner::<AllowRw<D, NUM>>(ptr::slice_from_raw_parts_mut(
+                (&raw mut this.buffer).cast(),
+                N,
+            ))
+        }?;
+        this.subscription = ptr::from_ref(done.get_ref()).cast();
+        #[cfg(feature = "registry")]
+        registry::set_owner(D::NUM, NUM, StaticRw::CLASS, ptr::from_mut(this).cast());
+        Ok(())
+    }
+
+    /// Unallows the buffer and returns the prefix the driver reported writing
+    /// through `done`, clamped to `N`. Returns no bytes if the driver has not
+    /// reported yet, if `done` is not the Subscription the buffer was allowed
+    /// with, or if another buffer has taken its place since, as the report
+    /// would then describe that buffer.
+    pub fn unallow<const SUBSCRIBE_NUM: u32, const ARG: usize>(
+        self: Pin<&mut Self>,
+        done: Pin<&Subscription<D, SUBSCRIBE_NUM, ARG>>,
+    ) -> &mut [u8] {
+        let this = unsafe { self.get_unchecked_mut() };
+        let returned = reclaim_if_holder::<AllowRw<D, NUM>>(ptr::from_mut(this).cast());
+        // Only `allow` can share the buffer again, which clears the report, so
+        // if the kernel returns it, it has been shared since `done` was cleared.
+        let shared = returned.is_some_and(|r| r.address == (&raw mut this.buffer).cast());
+        let len = match shared && ptr::eq(this.subscription, ptr::from_ref(done.get_ref()).cast()) {
+            true => done.written.0.take().map_or(0, |len| len.min(N)),
+            false => 0,
+        };
+        this.subscription = ptr::null();
+        let prefix = &mut this.buffer[..len];
+        // SAFETY: The driver reported writing these bytes while the buffer was
+        // shared, and the buffer is no longer shared.
+        unsafe { &mut *(ptr::from_mut(prefix) as *mut [u8]) }
+    }
+}
+
+/// Records argument `ARG` of driver `D`'s upcall with subscribe number
+/// `SUBSCRIBE_NUM`, which the driver uses to report how many bytes it wrote.
+pub struct Subscription<D: Driver, const SUBSCRIBE_NUM: u32, const ARG: usize> {
+    _driver: PhantomData<D>,
+    _pinned: PhantomPinned,
+    written: Written<ARG>,
+}
+
+// Not public, so only the kernel can call its `Upcall::upcall`.
+#[derive(Default)]
+struct Written<const ARG: usize>(Cell<Option<usize>>);
+
+impl<const ARG: usize> Upcall for Written<ARG> {
+    fn upcall(&self, args: [u32; 3]) {
+        self.0.set(Some(args[ARG] as usize));
+    }
+}
+
+impl<D: Driver, const SUBSCRIBE_NUM: u32, const ARG: usize> Default
+    for Subscription<D, SUBSCRIBE_NUM, ARG>
+{
+    fn default() -> Subscription<D, SUBSCRIBE_NUM, ARG> {
+        const { assert!(ARG < 3) };
+        Subscription {
+            _driver: PhantomData,
+            _pinned: PhantomPinned,
+            written: Written::default(),
+        }
+    }
+}
+
+// Same surprising semantics as `no_dynamic::Subscription`: a Subscription that
+// is never subscribed will still clear its subscribe ID on drop.
+impl<D: Driver, const SUBSCRIBE_NUM: u32, const ARG: usize> Drop
+    for Subscription<D, SUBSCRIBE_NUM, ARG>
+{
+    fn drop(&mut self) {
+        unsubscribe(D::NUM, SUBSCRIBE_NUM);
+    }
+}
+
+impl<D: Driver, const SUBSCRIBE_NUM: u32, const ARG: usize> Subscription<D, SUBSCRIBE_NUM, ARG> {
+    pub fn subscribe(self: Pin<&Self>) -> Result<(), ErrorCode> {
+        unsafe { subscribe_inner(D::NUM, SUBSCRIBE_NUM, &self.get_ref().written) }
+    }
+
+    pub fn unsubscribe(self: Pin<&Self>) {
+        unsubscribe(D::NUM, SUBSCRIBE_NUM);
+    }
+
+    /// The length the driver reported since a Buffer was last allowed with
+    /// this Subscription, if it has.
+    pub fn received(self: Pin<&Self>) -> Option<usize> {
+        self.written.0.get()
+    }
+}
@@ -0,0 +1,78 @@
+//! Typed views over the bytes of a buffer, for decoding kernel responses and
+//! encoding requests without manual offset arithmetic. A buffer is viewed as a
+//! header of type `H` followed by a payload of raw bytes. The header is checked
+//! against the buffer's length and alignment: a buffer that is too short fails
+//! with `Size`, and a misaligned one with `Invalid`.
+//!
+//! The views are methods on the `no_dynamic`, `dynamic_type` and `full_dynamic`
+//! Buffers (`view`, `view_mut` and `write_header`), which only hand out a view
+//! of a buffer the kernel cannot access: `no_dynamic` unallows first, and the
+//! others fail with `Busy` while the buffer is shared. `read_header` copies the
+//! header out of a buffer that is shared userspace-readable, without a view.
+
+use crate::{ErrorCode, read_untorn};
+use zerocopy::{
+    CastError, ConvertError, FromBytes, Immutable, IntoBytes, KnownLayout, Ref, SizeError,
+};
+
+/// A buffer viewed as a header followed by a payload.
+pub struct View<'b, H> {
+    pub header: Ref<&'b [u8], H>,
+    pub payload: &'b [u8],
+}
+
+/// A mutable buffer viewed as a header followed by a payload.
+pub struct ViewMut<'b, H> {
+    pub header: Ref<&'b mut [u8], H>,
+    pub payload: &'b mut [u8],
+}
+
+/// Views `bytes` as an `H` followed by the remaining bytes.
+pub(crate) fn parse<H: FromBytes + KnownLayout + Immutable>(
+    bytes: &[u8],
+) -> Result<View<'_, H>, ErrorCode> {
+    let (header, payload) = Ref::from_prefix(bytes).map_err(cast_error)?;
+    Ok(View { header, payload })
+}
+
+/// Views `bytes` as a mutable `H` followed by the remaining bytes.
+pub(crate) fn parse_mut<H: FromBytes + IntoBytes + KnownLayout + Immutable>(
+    bytes: &mut [u8],
+) -> Result<ViewMut<'_, H>, ErrorCode> {
+    let (header, payload) = Ref::from_prefix(bytes).map_err(cast_error)?;
+    Ok(ViewMut { header, payload })
+}
+
+/// Writes `header` to the start of `bytes` and returns the payload bytes after
+/// it.
+pub(crate) fn write<H: IntoBytes + KnownLayout + Immutable>(
+    bytes: &mut [u8],
+    header: H,
+) -> Result<&mut [u8], ErrorCode> {
+    let (mut dest, payload) = Ref::<_, H>::from_prefix(bytes).map_err(cast_error)?;
+    Ref::write(&mut dest, header);
+    Ok(payload)
+}
+
+/// Copies an `H` from the start of the `len` bytes at `src`, which the kernel
+/// may be writing. Fails with `Size` if `H` does not fit, and with `Busy` if
+/// the kernel kept changing it.
+///
+/// # Safety
+/// `src` must be valid for reads of `len` bytes.
+pub(crate) unsafe fn read_header<H: FromBytes + IntoBytes + Immutable>(
+    src: *const u8,
+    len: usize,
+) -> Result<H, ErrorCode> {
+    if size_of::<H>() > len {
+        return Err(ErrorCode::Size);
+    }
+    unsafe { read_untorn(src.cast()) }.ok_or(ErrorCode::Busy)
+}
+
+fn cast_error<S, H: ?Sized + KnownLayout>(error: CastError<S, H>) -> ErrorCode {
+    match error {
+        ConvertError::Alignment(_) => ErrorCode::Invalid,
+        ConvertError::Size(SizeError { .. }) => ErrorCode::Size,
+    }
+}
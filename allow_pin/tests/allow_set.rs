@@ -0,0 +1,116 @@
+use allow_pin::fake_kernel::{self, Syscall};
+use allow_pin::{
+    AllowRo, AllowRw, AllowSet, DriverNum, DynamicType, ErrorCode, dynamic_type, full_dynamic,
+    no_dynamic,
+};
+use core::pin::pin;
+
+#[test]
+fn allow_set_releases_all() {
+    fake_kernel::reset();
+    let mut command = pin!(dynamic_type::Buffer::<AllowRo<DriverNum<1>, 0>, [u8; 2]>::from(*b"hi"));
+    let mut response =
+        pin!(dynamic_type::Buffer::<AllowRw<DriverNum<1>, 1>, [u8; 4]>::from([0; 4]));
+    let set = AllowSet::allow((command.as_mut(), response.as_mut()))
+        .map_err(|(_, error)| error)
+        .unwrap();
+    assert_eq!(fake_kernel::allow_table().len(), 2);
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 1, 0, &[1]));
+    let (_, response) = set.release();
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert_eq!(response.as_ref().buffer(), Some(&[1, 0, 0, 0]));
+
+    drop(AllowSet::allow((command.as_mut(), response)));
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn allow_set_rolls_back_on_failure() {
+    fake_kernel::reset();
+    let mut command = pin!(dynamic_type::Buffer::<AllowRo<DriverNum<1>, 0>, [u8; 2]>::from(*b"hi"));
+    let mut response =
+        pin!(dynamic_type::Buffer::<AllowRw<DriverNum<1>, 1>, [u8; 4]>::from([0; 4]));
+    // `response` was shared before the set, so the set fails on it and must
+    // leave it shared.
+    assert_eq!(response.as_mut().allow(DynamicType::Rw), Ok(()));
+    fake_kernel::take_log();
+    let Err((_, error)) = AllowSet::allow((command.as_mut(), response.as_mut())) else {
+        panic!("allowing the set should fail");
+    };
+    assert_eq!(error, ErrorCode::Already);
+    assert_eq!(command.as_ref().share_status(), None);
+    assert_eq!(response.as_ref().share_status(), Some(DynamicType::Rw));
+    assert_eq!(fake_kernel::take_log().len(), 2);
+    assert_eq!(
+        fake_kernel::allow_table()
+            .iter()
+            .map(|&(driver_num, buffer_num, allow_type, _)| (driver_num, buffer_num, allow_type))
+            .collect::<Vec<_>>(),
+        [(1, 1, DynamicType::Rw)]
+    );
+
+    // A failure reported by the kernel also rolls back.
+    response.as_mut().unallow();
+    let mut foreign = pin!(dynamic_type::Buffer::<AllowRo<DriverNum<1>, 0>, [u8; 1]>::from([0]));
+    assert_eq!(foreign.as_mut().allow(DynamicType::Ro), Ok(()));
+    let foreign_shared = fake_kernel::shared(DynamicType::Ro, 1, 0).unwrap();
+    let mut status = pin!(dynamic_type::Buffer::<AllowRo<DriverNum<1>, 2>, [u8; 1]>::from([0]));
+    let result = AllowSet::allow((status.as_mut(), command.as_mut(), response.as_mut()));
+    assert_eq!(
+        result.err().map(|(_, error)| error),
+        Some(ErrorCode::ForeignBufferSwappedOut)
+    );
+    assert_eq!(status.as_ref().share_status(), None);
+    assert_eq!(fake_kernel::shared(DynamicType::Ro, 1, 2), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Ro, 1, 0),
+        Some(foreign_shared)
+    );
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 1), None);
+}
+
+#[test]
+fn no_dynamic_members() {
+    fake_kernel::reset();
+    let mut command = pin!(no_dynamic::Buffer::<AllowRo<DriverNum<1>, 0>, [u8; 2]>::from(*b"hi"));
+    let mut response = pin!(no_dynamic::Buffer::<AllowRw<DriverNum<1>, 1>, [u8; 4]>::from([0; 4]));
+    fake_kernel::pass_next();
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    let Err((_, error)) = AllowSet::allow((command.as_mut(), response.as_mut())) else {
+        panic!("allowing the set should fail");
+    };
+    assert_eq!(error, ErrorCode::NoMem);
+    assert_eq!(fake_kernel::allow_table(), []);
+    fake_kernel::take_log();
+
+    let set = AllowSet::allow((command.as_mut(), response.as_mut()))
+        .map_err(|(_, error)| error)
+        .unwrap();
+    assert_eq!(fake_kernel::allow_table().len(), 2);
+    drop(set);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn mixed_members_name_full_dynamic_slots() {
+    fake_kernel::reset();
+    type Command = AllowRo<DriverNum<1>, 0>;
+    type Response = AllowRw<DriverNum<1>, 1>;
+    let mut command = pin!(full_dynamic::Buffer::from(*b"hi"));
+    let mut response = pin!(no_dynamic::Buffer::<Response, [u8; 4]>::from([0; 4]));
+    let set = AllowSet::<(Command, Response), _>::allow((command.as_mut(), response.as_mut()))
+        .map_err(|(_, error)| error)
+        .unwrap();
+    assert!(fake_kernel::shared(DynamicType::Ro, 1, 0).is_some());
+    assert!(fake_kernel::shared(DynamicType::Rw, 1, 1).is_some());
+    let (command, _) = set.release();
+    assert_eq!(command.as_ref().buffer(), Some(b"hi"));
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert_eq!(
+        fake_kernel::take_log()[2..],
+        [
+            Syscall::unallow(DynamicType::Ro, 1, 0),
+            Syscall::unallow(DynamicType::Rw, 1, 1),
+        ]
+    );
+}
@@ -0,0 +1,120 @@
+use allow_pin::blocking::allow_command_wait;
+use allow_pin::fake_kernel::{self, Syscall};
+use allow_pin::no_dynamic::{Buffer, Subscription};
+use allow_pin::{AllowRo, AllowRw, DriverNum, DynamicType, ErrorCode};
+use core::cell::Cell;
+use core::pin::pin;
+
+type Ro = AllowRo<DriverNum<1>, 2>;
+type Rw = AllowRw<DriverNum<1>, 3>;
+
+#[test]
+fn reads_into_a_buffer() {
+    fake_kernel::reset();
+    let buffer = pin!(Buffer::<Rw, [u8; 4]>::from([0; 4]));
+    fake_kernel::on_yield(|| {
+        assert!(fake_kernel::write(DynamicType::Rw, 1, 3, 0, &[7, 8]));
+        assert!(fake_kernel::upcall(1, 0, [0, 2, 0]));
+    });
+    let (args, buffer) = allow_command_wait(buffer, 1, 0, 5, 2, 0).unwrap();
+    assert_eq!(args, [0, 2, 0]);
+    assert_eq!(*buffer, [7, 8, 0, 0]);
+    let log = fake_kernel::take_log();
+    assert!(matches!(
+        log[..],
+        [
+            Syscall::Allow { len: 4, .. },
+            Syscall::Subscribe { driver_num: 1, subscribe_num: 0, .. },
+            Syscall::Command {
+                driver_num: 1,
+                command_num: 5,
+                arg0: 2,
+                arg1: 0
+            },
+            Syscall::YieldWaitFor {
+                driver_num: 1,
+                subscribe_num: 0
+            },
+            unsubscribe,
+            unallow,
+        ] if unsubscribe == Syscall::unsubscribe(1, 0)
+            && unallow == Syscall::unallow(DynamicType::Rw, 1, 3)
+    ));
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn shares_ro_and_rw_buffers() {
+    fake_kernel::reset();
+    let ro = pin!(Buffer::<Ro, [u8; 2]>::from([1, 2]));
+    let rw = pin!(Buffer::<Rw, [u8; 2]>::from([0; 2]));
+    fake_kernel::on_yield(|| {
+        assert!(fake_kernel::shared(DynamicType::Ro, 1, 2).is_some());
+        assert!(fake_kernel::write(DynamicType::Rw, 1, 3, 0, &[3, 4]));
+        assert!(fake_kernel::upcall(1, 1, [0; 3]));
+    });
+    let (_, (ro, rw)) = allow_command_wait((ro.into_ref(), rw), 1, 1, 1, 0, 0).unwrap();
+    assert_eq!((*ro, *rw), ([1, 2], [3, 4]));
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert!(!fake_kernel::subscribed(1, 1));
+}
+
+#[test]
+fn waits_for_its_upcall_only() {
+    fake_kernel::reset();
+    let other = pin!(Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+    assert_eq!(other.as_ref().subscribe(), Ok(()));
+    fake_kernel::on_yield(|| ());
+    fake_kernel::on_yield(|| assert!(fake_kernel::upcall(1, 1, [8; 3])));
+    fake_kernel::on_yield(|| assert!(fake_kernel::upcall(1, 0, [9; 3])));
+    assert_eq!(allow_command_wait((), 1, 0, 1, 0, 0), Ok(([9; 3], ())));
+    assert_eq!(other.as_ref().upcall().get(), Some([8; 3]));
+    let log = fake_kernel::take_log();
+    assert_eq!(
+        log.iter()
+            .filter(|call| matches!(call, Syscall::YieldWaitFor { .. }))
+            .count(),
+        1
+    );
+    assert!(!fake_kernel::subscribed(1, 0));
+}
+
+#[test]
+fn failed_command_unsubscribes() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    fake_kernel::pass_next();
+    fake_kernel::pass_next();
+    fake_kernel::fail_next(ErrorCode::Busy);
+    assert_eq!(
+        allow_command_wait(buffer.as_mut(), 1, 0, 1, 0, 0).map(|_| ()),
+        Err(ErrorCode::Busy)
+    );
+    assert!(!fake_kernel::subscribed(1, 0));
+    assert!(
+        !fake_kernel::take_log()
+            .iter()
+            .any(|call| matches!(call, Syscall::YieldWaitFor { .. }))
+    );
+    // The buffer stays shared until it is accessed, like any no_dynamic Buffer.
+    assert!(fake_kernel::shared(DynamicType::Rw, 1, 3).is_some());
+    assert_eq!(*buffer.as_mut().buffer_mut(), [1; 4]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn failed_allow_does_not_subscribe() {
+    fake_kernel::reset();
+    let ro = pin!(Buffer::<Ro, [u8; 2]>::from([0; 2]));
+    let rw = pin!(Buffer::<Rw, [u8; 2]>::from([0; 2]));
+    fake_kernel::pass_next();
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    assert_eq!(
+        allow_command_wait((ro.into_ref(), rw), 1, 0, 1, 0, 0).map(|_| ()),
+        Err(ErrorCode::NoMem)
+    );
+    assert!(matches!(
+        fake_kernel::take_log()[..],
+        [Syscall::Allow { .. }, Syscall::Allow { .. }]
+    ));
+}
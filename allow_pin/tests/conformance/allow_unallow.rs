@@ -0,0 +1,30 @@
+use crate::{Ro, Rw};
+use allow_pin::fake_kernel;
+use allow_pin::{AllowBuffer as _, DynamicType, Implementation};
+use core::pin::pin;
+
+pub fn allow_then_unallow<I: Implementation>() {
+    fake_kernel::reset();
+    let mut buffer = pin!(I::Buffer::<Rw, [u8; 4]>::from([0; 4]));
+    assert_eq!(buffer.as_mut().allow(), Ok(()));
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 2).unwrap().len, 4);
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, b"abcd"));
+    assert_eq!(buffer.as_mut().unallow(), b"abcd");
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+pub fn unallow_when_not_shared<I: Implementation>() {
+    fake_kernel::reset();
+    let mut buffer = pin!(I::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    assert_eq!(*buffer.as_mut().unallow(), [1; 4]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+pub fn read_only<I: Implementation>() {
+    fake_kernel::reset();
+    let mut buffer = pin!(I::Buffer::<Ro, [u8; 2]>::from(*b"hi"));
+    assert_eq!(buffer.as_mut().allow(), Ok(()));
+    assert_eq!(fake_kernel::shared(DynamicType::Ro, 1, 1).unwrap().len, 2);
+    assert_eq!(buffer.as_mut().unallow(), b"hi");
+    assert_eq!(fake_kernel::allow_table(), []);
+}
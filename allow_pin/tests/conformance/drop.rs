@@ -0,0 +1,26 @@
+use crate::Rw;
+use allow_pin::fake_kernel;
+use allow_pin::{AllowBuffer as _, Implementation};
+use core::pin::pin;
+
+pub fn drop_unallows<I: Implementation>() {
+    fake_kernel::reset();
+    {
+        let mut buffer = pin!(I::Buffer::<Rw, [u8; 4]>::from([0; 4]));
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+    }
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+pub fn drop_after_replace_with<I: Implementation>() {
+    fake_kernel::reset();
+    let mut a = pin!(I::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    {
+        let mut b = pin!(I::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+        assert_eq!(a.as_mut().allow(), Ok(()));
+        let (_, result) = a.as_mut().replace_with(b.as_mut());
+        assert_eq!(result, Ok(()));
+    }
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert_eq!(*a.as_mut().unallow(), [1; 4]);
+}
@@ -0,0 +1,16 @@
+use crate::Rw;
+use allow_pin::fake_kernel;
+use allow_pin::{AllowBuffer as _, ErrorCode, Implementation};
+use core::pin::pin;
+
+pub fn failed_allow_leaves_buffer_unshared<I: Implementation>() {
+    fake_kernel::reset();
+    let mut buffer = pin!(I::Buffer::<Rw, [u8; 4]>::from([0; 4]));
+    fake_kernel::fail_next(ErrorCode::NoDevice);
+    assert_eq!(buffer.as_mut().allow(), Err(ErrorCode::NoDevice));
+    assert_eq!(fake_kernel::allow_table(), []);
+    // The failed allow must not be remembered as a share.
+    assert_eq!(buffer.as_mut().allow(), Ok(()));
+    assert_eq!(*buffer.as_mut().unallow(), [0; 4]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
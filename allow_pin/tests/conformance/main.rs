@@ -0,0 +1,89 @@
+//! Tests that every `Implementation` behaves the same through the
+//! `AllowBuffer` and `AllowSubscription` traits. Each scenario module holds
+//! generic tests, which `conformance!` instantiates once per implementation.
+//! Where the implementations differ, `Expected` states what each one does, and
+//! `syscalls` checks the exact system calls against it.
+
+mod allow_unallow;
+mod drop;
+mod failed_allow;
+mod replace_with;
+mod subscription;
+mod syscalls;
+
+use allow_pin::dynamic_type::DynamicTypeImpl;
+use allow_pin::full_dynamic::FullDynamicImpl;
+use allow_pin::no_dynamic::NoDynamicImpl;
+use allow_pin::{DriverNum, ErrorCode, Implementation};
+
+type Ro = allow_pin::AllowRo<DriverNum<1>, 1>;
+type Rw = allow_pin::AllowRw<DriverNum<1>, 2>;
+
+/// How an implementation differs from the others.
+trait Expected: Implementation {
+    /// Whether unallowing or dropping a Buffer that was never shared makes an
+    /// Allow call. `no_dynamic` does not track sharing, so it does unless the
+    /// `registry` feature is enabled.
+    const UNALLOWS_UNSHARED: bool;
+    /// The result of allowing a Buffer while another Buffer is shared with the
+    /// same allow ID. `no_dynamic` swaps the other Buffer out, while the
+    /// others share it again and fail.
+    const ALLOW_OVER_FOREIGN: Result<(), ErrorCode>;
+}
+
+impl Expected for NoDynamicImpl {
+    const UNALLOWS_UNSHARED: bool = cfg!(not(feature = "registry"));
+    const ALLOW_OVER_FOREIGN: Result<(), ErrorCode> = Ok(());
+}
+
+impl Expected for DynamicTypeImpl {
+    const UNALLOWS_UNSHARED: bool = false;
+    const ALLOW_OVER_FOREIGN: Result<(), ErrorCode> = Err(ErrorCode::ForeignBufferSwappedOut);
+}
+
+impl Expected for FullDynamicImpl {
+    const UNALLOWS_UNSHARED: bool = false;
+    const ALLOW_OVER_FOREIGN: Result<(), ErrorCode> = Err(ErrorCode::ForeignBufferSwappedOut);
+}
+
+macro_rules! tests {
+    ($implementation:ty; { $($scenario:ident::$test:ident),* $(,)? }) => {
+        $(
+            #[test]
+            fn $test() {
+                crate::$scenario::$test::<$implementation>();
+            }
+        )*
+    };
+}
+
+macro_rules! conformance {
+    ($($module:ident: $implementation:ty),*; $tests:tt) => {
+        $(
+            mod $module {
+                tests!($implementation; $tests);
+            }
+        )*
+    };
+}
+
+conformance!(
+    no_dynamic: crate::NoDynamicImpl,
+    dynamic_type: crate::DynamicTypeImpl,
+    full_dynamic: crate::FullDynamicImpl;
+    {
+        allow_unallow::allow_then_unallow,
+        allow_unallow::unallow_when_not_shared,
+        allow_unallow::read_only,
+        drop::drop_unallows,
+        drop::drop_after_replace_with,
+        failed_allow::failed_allow_leaves_buffer_unshared,
+        replace_with::replace_shared_buffer,
+        replace_with::replace_unshared_buffer,
+        subscription::subscribe_and_upcall,
+        subscription::drop_unsubscribes,
+        syscalls::allow_unallow_syscalls,
+        syscalls::replace_with_is_one_allow,
+        syscalls::allow_while_another_buffer_is_shared,
+    }
+);
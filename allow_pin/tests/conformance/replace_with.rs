@@ -0,0 +1,42 @@
+use crate::Rw;
+use allow_pin::fake_kernel::{self, Shared};
+use allow_pin::{AllowBuffer as _, DynamicType, Implementation};
+use core::pin::pin;
+
+pub fn replace_shared_buffer<I: Implementation>() {
+    fake_kernel::reset();
+    let mut a = pin!(I::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(I::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+    let b_address = b.as_mut().unallow().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, b"abcd"));
+    let (old, result) = a.as_mut().replace_with(b.as_mut());
+    assert_eq!(result, Ok(()));
+    assert_eq!(old, b"abcd");
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared {
+            address: b_address,
+            len: 4
+        })
+    );
+    assert_eq!(*b.as_mut().unallow(), [2; 4]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+pub fn replace_unshared_buffer<I: Implementation>() {
+    fake_kernel::reset();
+    let mut a = pin!(I::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(I::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+    let b_address = b.as_mut().unallow().as_mut_ptr();
+    let (old, result) = a.as_mut().replace_with(b.as_mut());
+    assert_eq!(result, Ok(()));
+    assert_eq!(*old, [1; 4]);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared {
+            address: b_address,
+            len: 4
+        })
+    );
+}
@@ -0,0 +1,24 @@
+use allow_pin::fake_kernel;
+use allow_pin::{AllowSubscription as _, Implementation};
+use core::cell::Cell;
+use core::pin::pin;
+
+pub fn subscribe_and_upcall<I: Implementation>() {
+    fake_kernel::reset();
+    let mut done = pin!(I::Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+    assert_eq!(done.as_mut().subscribe(), Ok(()));
+    assert!(fake_kernel::subscribed(1, 1));
+    assert!(fake_kernel::upcall(1, 1, [1, 2, 3]));
+    assert_eq!(done.as_ref().upcall().get(), Some([1, 2, 3]));
+    done.as_mut().unsubscribe();
+    assert!(!fake_kernel::subscribed(1, 1));
+}
+
+pub fn drop_unsubscribes<I: Implementation>() {
+    fake_kernel::reset();
+    {
+        let mut done = pin!(I::Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+        assert_eq!(done.as_mut().subscribe(), Ok(()));
+    }
+    assert!(!fake_kernel::subscribed(1, 1));
+}
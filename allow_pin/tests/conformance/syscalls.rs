@@ -0,0 +1,72 @@
+use crate::{Expected, Rw};
+use allow_pin::fake_kernel::{self, Shared, Syscall};
+use allow_pin::{AllowBuffer as _, DynamicType};
+use core::pin::pin;
+
+fn allow(address: *mut u8) -> Syscall {
+    Syscall::Allow {
+        allow_type: DynamicType::Rw,
+        driver_num: 1,
+        buffer_num: 2,
+        address,
+        len: 4,
+    }
+}
+
+pub fn allow_unallow_syscalls<I: Expected>() {
+    fake_kernel::reset();
+    let unallow_unshared = match I::UNALLOWS_UNSHARED {
+        true => vec![Syscall::unallow(DynamicType::Rw, 1, 2)],
+        false => vec![],
+    };
+    {
+        let mut buffer = pin!(I::Buffer::<Rw, [u8; 4]>::from([0; 4]));
+        let address = buffer.as_mut().unallow().as_mut_ptr();
+        assert_eq!(fake_kernel::take_log(), unallow_unshared);
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        buffer.as_mut().unallow();
+        assert_eq!(
+            fake_kernel::take_log(),
+            [allow(address), Syscall::unallow(DynamicType::Rw, 1, 2)]
+        );
+    }
+    // Dropping the unshared Buffer.
+    assert_eq!(fake_kernel::take_log(), unallow_unshared);
+}
+
+pub fn replace_with_is_one_allow<I: Expected>() {
+    fake_kernel::reset();
+    let mut a = pin!(I::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(I::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+    let b_address = b.as_mut().unallow().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    fake_kernel::take_log();
+    let (_, result) = a.as_mut().replace_with(b.as_mut());
+    assert_eq!(result, Ok(()));
+    assert_eq!(fake_kernel::take_log(), [allow(b_address)]);
+}
+
+pub fn allow_while_another_buffer_is_shared<I: Expected>() {
+    fake_kernel::reset();
+    let mut a = pin!(I::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(I::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+    let a_address = a.as_mut().unallow().as_mut_ptr();
+    let b_address = b.as_mut().unallow().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    fake_kernel::take_log();
+    assert_eq!(b.as_mut().allow(), I::ALLOW_OVER_FOREIGN);
+    let (log, holder) = match I::ALLOW_OVER_FOREIGN {
+        // `a` was swapped out.
+        Ok(()) => (vec![allow(b_address)], b_address),
+        // `a` was shared again in place of `b`.
+        Err(_) => (vec![allow(b_address), allow(a_address)], a_address),
+    };
+    assert_eq!(fake_kernel::take_log(), log);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared {
+            address: holder,
+            len: 4
+        })
+    );
+}
@@ -0,0 +1,278 @@
+use allow_pin::dynamic_type::*;
+use allow_pin::fake_kernel::{self, Shared, Syscall};
+use allow_pin::{DriverNum, no_dynamic};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[test]
+fn allow_then_unallow() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+    let address = buffer.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw), Ok(()));
+    assert_eq!(buffer.as_ref().share_status(), Some(DynamicType::Rw));
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared { address, len: 4 })
+    );
+    assert_eq!(*buffer.as_mut().unallow(), [0; 4]);
+    assert_eq!(buffer.as_ref().share_status(), None);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [
+            Syscall::Allow {
+                allow_type: DynamicType::Rw,
+                driver_num: 1,
+                buffer_num: 2,
+                address,
+                len: 4,
+            },
+            Syscall::unallow(DynamicType::Rw, 1, 2),
+        ]
+    );
+}
+
+#[test]
+fn allow_type_is_chosen_at_runtime() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+    for allow_type in [
+        DynamicType::Ro,
+        DynamicType::Rw,
+        DynamicType::UserspaceReadable,
+    ] {
+        assert_eq!(buffer.as_mut().allow(allow_type), Ok(()));
+        assert_eq!(buffer.as_ref().share_status(), Some(allow_type));
+        assert!(fake_kernel::shared(allow_type, 1, 2).is_some());
+        buffer.as_mut().unallow();
+        assert_eq!(
+            fake_kernel::take_log().last(),
+            Some(&Syscall::unallow(allow_type, 1, 2))
+        );
+    }
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn double_allow_is_rejected() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+    assert_eq!(buffer.as_mut().allow(DynamicType::Ro), Ok(()));
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::Ro),
+        Err(ErrorCode::Already)
+    );
+    assert_eq!(fake_kernel::take_log().len(), 1);
+}
+
+#[test]
+fn drop_unallows_only_if_shared() {
+    fake_kernel::reset();
+    drop(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+    assert_eq!(fake_kernel::take_log(), []);
+    {
+        let mut buffer = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+        assert_eq!(buffer.as_mut().allow(DynamicType::Ro), Ok(()));
+        fake_kernel::take_log();
+    }
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Ro, 1, 2)]
+    );
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn failed_allow() {
+    fake_kernel::reset();
+    {
+        let mut buffer = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+        fake_kernel::fail_next(ErrorCode::NoDevice);
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::Rw),
+            Err(ErrorCode::NoDevice)
+        );
+        assert_eq!(buffer.as_ref().share_status(), None);
+    }
+    assert_eq!(fake_kernel::take_log().len(), 1);
+}
+
+#[test]
+fn subscription() {
+    fake_kernel::reset();
+    drop(Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+    assert_eq!(fake_kernel::take_log(), []);
+    {
+        let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+        assert_eq!(done.as_mut().subscribe(), Ok(()));
+        assert_eq!(done.as_mut().subscribe(), Err(ErrorCode::Already));
+        assert!(done.as_ref().is_subscribed());
+        assert!(fake_kernel::upcall(1, 1, [4, 5, 6]));
+        assert_eq!(done.as_ref().upcall().get(), Some([4, 5, 6]));
+        done.as_mut().unsubscribe();
+        assert!(!fake_kernel::upcall(1, 1, [7, 8, 9]));
+        assert_eq!(done.as_ref().upcall().get(), Some([4, 5, 6]));
+    }
+    let log = fake_kernel::take_log();
+    assert_eq!(log.len(), 2);
+    assert!(!log[0].is_unsubscribe());
+    assert_eq!(log[1], Syscall::unsubscribe(1, 1));
+}
+
+#[test]
+fn userspace_readable_reads_without_unallowing() {
+    fake_kernel::reset();
+    let mut rw = pin!(Buffer::<Allow<DriverNum<2>, 1>, [u8; 4]>::from([0; 4]));
+    assert_eq!(rw.as_mut().allow(DynamicType::Rw), Ok(()));
+    assert_eq!(rw.as_mut().read(), None);
+    assert_eq!(rw.as_mut().read_volatile(0, &mut [0; 4]), None);
+    let mut buffer = pin!(Buffer::<Allow<DriverNum<2>, 0>, [u8; 4]>::from([0; 4]));
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::UserspaceReadable),
+        Ok(())
+    );
+    assert!(fake_kernel::write(
+        DynamicType::UserspaceReadable,
+        2,
+        0,
+        1,
+        &[5, 6]
+    ));
+    assert_eq!(buffer.as_mut().read(), Some([0, 5, 6, 0]));
+    let mut middle = [0; 2];
+    assert_eq!(buffer.as_mut().read_volatile(1, &mut middle), Some(()));
+    assert_eq!(middle, [5, 6]);
+    assert_eq!(
+        buffer.as_ref().share_status(),
+        Some(DynamicType::UserspaceReadable)
+    );
+}
+
+#[test]
+fn allow_does_not_swap_out_a_foreign_buffer() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 2]>::from([2; 2]));
+    let a_address = a.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(DynamicType::Ro), Ok(()));
+    assert_eq!(
+        b.as_mut().allow(DynamicType::Ro),
+        Err(ErrorCode::ForeignBufferSwappedOut)
+    );
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Ro, 1, 2),
+        Some(Shared {
+            address: a_address,
+            len: 4
+        })
+    );
+    assert_eq!(b.as_ref().share_status(), None);
+    // Unallowing `a` while `b` was never shared leaves nothing shared.
+    assert_eq!(*a.as_mut().unallow(), [1; 4]);
+    assert_eq!(fake_kernel::shared(DynamicType::Ro, 1, 2), None);
+}
+
+#[test]
+fn failed_restore_of_a_foreign_buffer_unshares_both() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 2]>::from([2; 2]));
+    assert_eq!(a.as_mut().allow(DynamicType::Ro), Ok(()));
+    fake_kernel::take_log();
+    // `b`'s allow succeeds, but sharing `a` again fails.
+    fake_kernel::pass_next();
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    assert_eq!(b.as_mut().allow(DynamicType::Ro), Err(ErrorCode::NoMem));
+    assert_eq!(fake_kernel::take_log().len(), 3);
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert_eq!(b.as_ref().share_status(), None);
+    assert_eq!(*a.as_mut().unallow(), [1; 4]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn replace_with_restores_a_foreign_buffer() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 2]>::from([1; 2]));
+    let mut b = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 2]>::from([2; 2]));
+    let mut foreign = pin!(no_dynamic::Buffer::<AllowRw<DriverNum<1>, 2>, [u8; 2]>::from([3; 2]));
+    let foreign_address = foreign.as_mut().buffer_mut().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(DynamicType::Rw), Ok(()));
+    // no_dynamic does not check what it swaps out, so `a` is displaced while
+    // it still believes it is shared.
+    assert_eq!(foreign.as_mut().allow(), Ok(()));
+    let (old, result) = a.as_mut().replace_with(b.as_mut());
+    assert_eq!(result, Err(ErrorCode::ForeignBufferSwappedOut));
+    assert_eq!(*old, [1; 2]);
+    assert_eq!(b.as_ref().share_status(), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared {
+            address: foreign_address,
+            len: 2
+        })
+    );
+}
+
+#[test]
+fn replace_with_needs_a_shared_buffer() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 2]>::from([1; 2]));
+    let mut b = pin!(Buffer::<Allow<DriverNum<1>, 2>, [u8; 2]>::from([2; 2]));
+    // `a` is not shared, so there is no class to share `b` with.
+    let (_, result) = a.as_mut().replace_with(b.as_mut());
+    assert_eq!(result, Err(ErrorCode::Invalid));
+    assert_eq!(b.as_mut().allow(DynamicType::Ro), Ok(()));
+    assert_eq!(
+        a.as_mut().allow(DynamicType::Ro),
+        Err(ErrorCode::ForeignBufferSwappedOut)
+    );
+    let (_, result) = a.as_mut().replace_with(b.as_mut());
+    assert_eq!(result, Err(ErrorCode::Already));
+    assert_eq!(fake_kernel::take_log().len(), 3);
+}
+
+#[test]
+fn borrowed_buffer() {
+    fake_kernel::reset();
+    let data = *b"hi";
+    BorrowedBuffer::<Allow<DriverNum<1>, 1>>::with(&data, |mut buffer| {
+        // The slice was borrowed immutably, so it can only be shared read-only.
+        assert_eq!(buffer.as_mut().buffer_mut(), None);
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::Rw),
+            Err(ErrorCode::Invalid)
+        );
+        assert_eq!(buffer.as_mut().allow(DynamicType::Ro), Ok(()));
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::Ro),
+            Err(ErrorCode::Already)
+        );
+        assert_eq!(buffer.as_ref().buffer(), None);
+        assert_eq!(
+            fake_kernel::shared(DynamicType::Ro, 1, 1),
+            Some(Shared {
+                address: data.as_ptr().cast_mut(),
+                len: 2
+            })
+        );
+        assert_eq!(buffer.as_mut().unallow(), b"hi");
+        assert_eq!(buffer.as_ref().share_status(), None);
+        assert_eq!(buffer.as_mut().allow(DynamicType::Ro), Ok(()));
+        fake_kernel::take_log();
+    });
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Ro, 1, 1)]
+    );
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn read_volatile_rejects_an_overflowing_offset() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Allow<DriverNum<2>, 0>, [u8; 4]>::from([0; 4]));
+    let _ = buffer.as_mut().read_volatile(usize::MAX, &mut [0; 2]);
+}
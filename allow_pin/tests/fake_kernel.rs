@@ -0,0 +1,166 @@
+use allow_pin::fake_kernel::{self, Shared, Syscall};
+use allow_pin::{DynamicType, ErrorCode, command, yield_wait, yield_wait_for};
+
+#[test]
+fn command_is_logged() {
+    fake_kernel::reset();
+    assert_eq!(command(0x1, 0x1, 14, 0), Ok(()));
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::Command {
+            driver_num: 0x1,
+            command_num: 0x1,
+            arg0: 14,
+            arg1: 0
+        }]
+    );
+    assert_eq!(fake_kernel::take_log(), []);
+}
+
+#[test]
+fn injected_failures() {
+    fake_kernel::reset();
+    fake_kernel::fail_next(ErrorCode::NoDevice);
+    fake_kernel::fail_next(ErrorCode::Busy);
+    assert_eq!(command(0x1, 0x1, 14, 0), Err(ErrorCode::NoDevice));
+    assert_eq!(command(0x1, 0x1, 14, 0), Err(ErrorCode::Busy));
+    assert_eq!(command(0x1, 0x1, 14, 0), Ok(()));
+    assert_eq!(fake_kernel::take_log().len(), 3);
+}
+
+#[test]
+fn ro_and_rw_ids_are_separate() {
+    use allow_pin::full_dynamic::Buffer;
+    use core::pin::pin;
+    fake_kernel::reset();
+    let mut ro = pin!(Buffer::from([0u8; 2]));
+    let mut rw = pin!(Buffer::from([0u8; 3]));
+    let ro_address = ro.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    let rw_address = rw.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    assert_eq!(ro.as_mut().allow(DynamicType::Ro, 5, 0), Ok(()));
+    assert_eq!(rw.as_mut().allow(DynamicType::Rw, 5, 0), Ok(()));
+    assert_eq!(
+        fake_kernel::allow_table(),
+        [
+            (
+                5,
+                0,
+                DynamicType::Rw,
+                Shared {
+                    address: rw_address,
+                    len: 3
+                }
+            ),
+            (
+                5,
+                0,
+                DynamicType::Ro,
+                Shared {
+                    address: ro_address,
+                    len: 2
+                }
+            ),
+        ]
+    );
+    rw.as_mut().unallow();
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 5, 0), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Ro, 5, 0),
+        Some(Shared {
+            address: ro_address,
+            len: 2
+        })
+    );
+}
+
+#[test]
+fn scribble_writes_only_writable_buffers() {
+    use allow_pin::full_dynamic::Buffer;
+    use core::pin::pin;
+    fake_kernel::reset();
+    let mut ro = pin!(Buffer::from([0u8; 2]));
+    let mut rw = pin!(Buffer::from([0u8; 3]));
+    let mut ura = pin!(Buffer::from([0u8; 1]));
+    assert_eq!(ro.as_mut().allow(DynamicType::Ro, 5, 0), Ok(()));
+    assert_eq!(rw.as_mut().allow(DynamicType::Rw, 5, 0), Ok(()));
+    assert_eq!(
+        ura.as_mut().allow(DynamicType::UserspaceReadable, 5, 1),
+        Ok(())
+    );
+    assert_eq!(fake_kernel::scribble(), 2);
+    assert_eq!(*ro.as_mut().unallow(), [0; 2]);
+    assert_eq!(*rw.as_mut().unallow(), [fake_kernel::SCRIBBLE; 3]);
+    assert_eq!(*ura.as_mut().unallow(), [fake_kernel::SCRIBBLE; 1]);
+    assert_eq!(fake_kernel::scribble(), 0);
+}
+
+#[test]
+fn error_code_from_raw() {
+    assert_eq!(ErrorCode::from_raw(2), ErrorCode::Busy);
+    assert_eq!(ErrorCode::from_raw(6), ErrorCode::Invalid);
+    assert_eq!(ErrorCode::from_raw(13), ErrorCode::NoAck);
+    assert_eq!(ErrorCode::from_raw(0), ErrorCode::BadRVal);
+    assert_eq!(ErrorCode::from_raw(14), ErrorCode::BadRVal);
+    assert_eq!(u32::from(ErrorCode::Invalid), 6);
+}
+
+#[test]
+fn pass_next_delays_a_failure() {
+    fake_kernel::reset();
+    fake_kernel::pass_next();
+    fake_kernel::fail_next(ErrorCode::Busy);
+    assert_eq!(command(0x1, 0x1, 14, 0), Ok(()));
+    assert_eq!(command(0x1, 0x1, 14, 0), Err(ErrorCode::Busy));
+}
+
+#[test]
+fn yield_wait_runs_queued_actions() {
+    use core::cell::Cell;
+    use std::rc::Rc;
+    fake_kernel::reset();
+    let runs = Rc::new(Cell::new(0));
+    for _ in 0..2 {
+        let runs = runs.clone();
+        fake_kernel::on_yield(move || runs.set(runs.get() + 1));
+    }
+    yield_wait();
+    assert_eq!(runs.get(), 1);
+    yield_wait();
+    assert_eq!(runs.get(), 2);
+    assert_eq!(fake_kernel::take_log(), [Syscall::YieldWait; 2]);
+}
+
+#[test]
+#[should_panic(expected = "would block forever")]
+fn yield_wait_without_actions_panics() {
+    fake_kernel::reset();
+    yield_wait();
+}
+
+#[test]
+fn yield_wait_for_returns_its_upcall_arguments() {
+    fake_kernel::reset();
+    fake_kernel::on_yield(|| ());
+    fake_kernel::on_yield(|| assert!(fake_kernel::upcall(1, 2, [3, 4, 5])));
+    fake_kernel::on_yield(|| ());
+    assert_eq!(yield_wait_for(1, 2), [3, 4, 5]);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::YieldWaitFor {
+            driver_num: 1,
+            subscribe_num: 2
+        }]
+    );
+    // The last action is left for the next Yield.
+    yield_wait();
+    // Once the wait is over, the upcall is no longer delivered.
+    assert!(!fake_kernel::upcall(1, 2, [0; 3]));
+}
+
+#[test]
+#[should_panic(expected = "would block forever")]
+fn yield_wait_for_without_its_upcall_panics() {
+    fake_kernel::reset();
+    fake_kernel::on_yield(|| assert!(!fake_kernel::upcall(1, 3, [0; 3])));
+    yield_wait_for(1, 2);
+}
@@ -0,0 +1,406 @@
+use allow_pin::fake_kernel::{self, Shared, Syscall};
+use allow_pin::full_dynamic::*;
+use allow_pin::{AllowBuffer, DriverNum};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[test]
+fn allow_then_unallow() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    let address = buffer.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared { address, len: 4 })
+    );
+    assert_eq!(*buffer.as_mut().unallow(), [0; 4]);
+    assert_eq!(*buffer.as_mut().unallow(), [0; 4]);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [
+            Syscall::Allow {
+                allow_type: DynamicType::Rw,
+                driver_num: 1,
+                buffer_num: 2,
+                address,
+                len: 4,
+            },
+            Syscall::unallow(DynamicType::Rw, 1, 2),
+        ]
+    );
+}
+
+#[test]
+fn allow_range_shares_part_of_the_buffer() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 8]>::from([1, 2, 0, 0, 0, 0, 0, 0]));
+    let address = buffer.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    assert_eq!(
+        buffer.as_mut().allow_range(2..8, DynamicType::Rw, 1, 2),
+        Ok(())
+    );
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared {
+            address: address.wrapping_add(2),
+            len: 6
+        })
+    );
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, &[3, 4]));
+    assert_eq!(*buffer.as_mut().unallow(), [1, 2, 3, 4, 0, 0, 0, 0]);
+    assert_eq!(fake_kernel::allow_table(), []);
+
+    assert_eq!(
+        buffer
+            .as_mut()
+            .allow_slot_range::<AllowRo<DriverNum<1>, 3>>(8..8),
+        Ok(())
+    );
+    buffer.as_mut().unallow();
+    assert_eq!(
+        fake_kernel::take_log()[2..],
+        [
+            Syscall::Allow {
+                allow_type: DynamicType::Ro,
+                driver_num: 1,
+                buffer_num: 3,
+                address: address.wrapping_add(8),
+                len: 0,
+            },
+            Syscall::unallow(DynamicType::Ro, 1, 3),
+        ]
+    );
+}
+
+#[test]
+fn allow_range_outside_the_buffer() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    #[allow(clippy::reversed_empty_ranges)]
+    for range in [2..5, 5..5, 3..2] {
+        assert_eq!(
+            buffer.as_mut().allow_range(range, DynamicType::Rw, 1, 2),
+            Err(ErrorCode::Size)
+        );
+    }
+    assert_eq!(fake_kernel::take_log(), []);
+}
+
+#[test]
+fn range_swapped_out_by_a_foreign_buffer() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    let mut other = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    assert_eq!(
+        buffer.as_mut().allow_range(1..3, DynamicType::Rw, 1, 2),
+        Ok(())
+    );
+    assert_eq!(
+        other.as_mut().allow(DynamicType::Rw, 1, 2),
+        Err(ErrorCode::ForeignBufferSwappedOut)
+    );
+    // `other` shared `buffer`'s range again. The kernel returns that range when
+    // `buffer` is unallowed, which must not be mistaken for a foreign buffer.
+    buffer.as_mut().unallow();
+    assert_eq!(
+        fake_kernel::take_log().last(),
+        Some(&Syscall::unallow(DynamicType::Rw, 1, 2))
+    );
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn largest_tock_allow_ids_round_trip() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    for allow_type in [
+        DynamicType::Rw,
+        DynamicType::Ro,
+        DynamicType::UserspaceReadable,
+    ] {
+        for (driver_num, buffer_num) in [(0, 0), (0xFFFFF, 0xFF), (0x90003, 1)] {
+            assert_eq!(
+                buffer.as_mut().allow(allow_type, driver_num, buffer_num),
+                Ok(())
+            );
+            buffer.as_mut().unallow();
+            assert_eq!(
+                fake_kernel::take_log()[1],
+                Syscall::unallow(allow_type, driver_num, buffer_num)
+            );
+        }
+    }
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[cfg(not(feature = "unpacked_share_info"))]
+#[test]
+fn allow_ids_outside_tock_ranges_are_rejected() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::Rw, 0x100000, 0),
+        Err(ErrorCode::NoDevice)
+    );
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::Ro, 1, 0x100),
+        Err(ErrorCode::Invalid)
+    );
+    assert_eq!(fake_kernel::take_log(), []);
+    assert!(buffer.as_mut().buffer_mut().is_some());
+}
+
+// The share info of a Buffer is one word when packed, against three for the
+// unpacked fields (the layout before packing).
+#[test]
+fn share_info_size() {
+    let words = if cfg!(feature = "unpacked_share_info") {
+        3
+    } else {
+        1
+    };
+    assert_eq!(size_of::<Buffer<[u32; 0]>>(), words * size_of::<u32>());
+}
+
+#[test]
+fn double_allow_is_rejected() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    assert_eq!(buffer.as_mut().allow(DynamicType::Ro, 1, 2), Ok(()));
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::Ro, 1, 3),
+        Err(ErrorCode::Already)
+    );
+    assert_eq!(fake_kernel::take_log().len(), 1);
+}
+
+#[test]
+fn drop_unallows_the_shared_id() {
+    fake_kernel::reset();
+    drop(Buffer::<[u8; 4]>::from([0; 4]));
+    assert_eq!(fake_kernel::take_log(), []);
+    {
+        let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+        assert_eq!(buffer.as_mut().allow(DynamicType::Ro, 7, 3), Ok(()));
+        fake_kernel::take_log();
+    }
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Ro, 7, 3)]
+    );
+}
+
+#[test]
+fn failed_allow() {
+    fake_kernel::reset();
+    {
+        let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+        fake_kernel::fail_next(ErrorCode::NoDevice);
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::Rw, 1, 2),
+            Err(ErrorCode::NoDevice)
+        );
+        assert!(buffer.as_ref().buffer().is_some());
+    }
+    assert_eq!(fake_kernel::take_log().len(), 1);
+}
+
+#[test]
+fn subscription() {
+    fake_kernel::reset();
+    {
+        let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>>::default());
+        fake_kernel::fail_next(ErrorCode::NoDevice);
+        assert_eq!(done.as_mut().subscribe(4, 0), Err(ErrorCode::NoDevice));
+        assert!(!fake_kernel::subscribed(4, 0));
+        assert_eq!(done.as_mut().subscribe(4, 0), Ok(()));
+        assert_eq!(done.as_mut().subscribe(4, 1), Err(ErrorCode::Already));
+        assert!(fake_kernel::upcall(4, 0, [1, 0, 0]));
+        assert_eq!(done.as_ref().upcall().get(), Some([1, 0, 0]));
+        fake_kernel::take_log();
+    }
+    assert_eq!(fake_kernel::take_log(), [Syscall::unsubscribe(4, 0)]);
+}
+
+#[test]
+fn userspace_readable_reads_without_unallowing() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 2, 0), Ok(()));
+    assert_eq!(buffer.as_mut().read(), None);
+    buffer.as_mut().unallow();
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::UserspaceReadable, 2, 0),
+        Ok(())
+    );
+    assert!(fake_kernel::write(
+        DynamicType::UserspaceReadable,
+        2,
+        0,
+        0,
+        &[9]
+    ));
+    assert_eq!(buffer.as_mut().read(), Some([9, 0, 0, 0]));
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(*buffer.as_mut().unallow(), [9, 0, 0, 0]);
+}
+
+#[test]
+fn allow_does_not_swap_out_a_foreign_buffer() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<[u8; 4]>::from([1; 4]));
+    let mut b = pin!(Buffer::<[u8; 2]>::from([2; 2]));
+    let a_address = a.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+    assert_eq!(
+        b.as_mut().allow(DynamicType::Rw, 1, 2),
+        Err(ErrorCode::ForeignBufferSwappedOut)
+    );
+    // `a` is shared again and `b` is not tracked as shared.
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared {
+            address: a_address,
+            len: 4
+        })
+    );
+    assert_eq!(b.as_ref().buffer(), Some(&[2; 2]));
+}
+
+#[test]
+fn unallow_after_being_swapped_out() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<[u8; 4]>::from([1; 4]));
+    assert_eq!(a.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+    // Swap `a` out from under it, as code that does not track ownership could.
+    let mut foreign = [3u8; 2];
+    let foreign_address = foreign.as_mut_ptr();
+    allow_pin::share::scope::<allow_pin::share::AllowRw<allow_pin::DriverNum<1>, 2>, _, _>(
+        |handle| {
+            assert_eq!(handle.allow(&mut foreign), Ok(()));
+            // Unallowing `a` shares the foreign buffer again.
+            assert_eq!(*a.as_mut().unallow(), [1; 4]);
+            assert_eq!(
+                fake_kernel::shared(DynamicType::Rw, 1, 2),
+                Some(Shared {
+                    address: foreign_address,
+                    len: 2
+                })
+            );
+        },
+    );
+}
+
+#[test]
+fn static_buffer_ro() {
+    static DATA: [u8; 2] = *b"hi";
+    fake_kernel::reset();
+    let mut buffer = pin!(StaticBuffer::<[u8; 2]>::from(&DATA));
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+    assert_eq!(buffer.as_mut().allow(1, 1), Ok(()));
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Ro, 1, 1),
+        Some(Shared {
+            address: DATA.as_ptr().cast_mut(),
+            len: 2
+        })
+    );
+    assert_eq!(buffer.as_mut().unallow(), Some(&DATA));
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn static_buffer_rw_is_returned_on_take() {
+    fake_kernel::reset();
+    let data: &'static mut [u8; 4] = Box::leak(Box::new([0; 4]));
+    let address = data.as_mut_ptr();
+    let mut buffer = pin!(StaticBuffer::from(data));
+    assert_eq!(buffer.as_mut().allow(1, 2), Ok(()));
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared { address, len: 4 })
+    );
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 1, &[7, 8]));
+    let Some(StaticRef::Rw(data)) = buffer.as_mut().take() else {
+        panic!("take should return the read-write reference");
+    };
+    assert_eq!(*data, [0, 7, 8, 0]);
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(buffer.as_mut().allow(1, 2), Err(ErrorCode::NoMem));
+}
+
+#[test]
+fn borrowed_buffer() {
+    fake_kernel::reset();
+    let data = [0; 4];
+    BorrowedBuffer::with(&data, |mut buffer| {
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::Rw, 1, 2),
+            Err(ErrorCode::Invalid)
+        );
+        assert_eq!(buffer.as_mut().buffer_mut(), None);
+    });
+    let mut data = [0; 4];
+    let address = data.as_mut_ptr();
+    BorrowedBuffer::with_mut(&mut data, |mut buffer| {
+        assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+        assert_eq!(buffer.as_mut().buffer_mut(), None);
+        assert_eq!(
+            fake_kernel::shared(DynamicType::Rw, 1, 2),
+            Some(Shared { address, len: 4 })
+        );
+        assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 3, &[6]));
+    });
+    assert_eq!(data, [0, 0, 0, 6]);
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert_eq!(
+        fake_kernel::take_log().last(),
+        Some(&Syscall::unallow(DynamicType::Rw, 1, 2))
+    );
+}
+
+#[test]
+fn slot_replace_with_swaps_in_one_allow() {
+    type Slot = AllowRw<DriverNum<1>, 2>;
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<[u8; 4]>::from([1; 4]));
+    let mut b = pin!(Buffer::<[u8; 4]>::from([2; 4]));
+    assert_eq!(a.as_mut().allow_slot::<Slot>(), Ok(()));
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    let (old, result) = AllowBuffer::<Slot>::replace_with(a.as_mut(), b.as_mut());
+    assert_eq!(result, Err(ErrorCode::NoMem));
+    assert_eq!(*old, [1; 4]);
+    // The failed swap left `a` shared, so it was unallowed.
+    assert_eq!(
+        fake_kernel::take_log()[2..],
+        [Syscall::unallow(DynamicType::Rw, 1, 2)]
+    );
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert!(b.as_ref().buffer().is_some());
+
+    // `a` is shared with a different allow ID, so this unallows and allows.
+    assert_eq!(a.as_mut().allow(DynamicType::Rw, 1, 3), Ok(()));
+    fake_kernel::take_log();
+    let (_, result) = AllowBuffer::<Slot>::replace_with(a.as_mut(), b.as_mut());
+    assert_eq!(result, Ok(()));
+    assert_eq!(
+        fake_kernel::take_log()[0],
+        Syscall::unallow(DynamicType::Rw, 1, 3)
+    );
+    assert!(fake_kernel::shared(DynamicType::Rw, 1, 2).is_some());
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn read_volatile_rejects_an_overflowing_offset() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 4]>::from([0; 4]));
+    let _ = buffer.as_mut().read_volatile(usize::MAX, &mut [0; 2]);
+}
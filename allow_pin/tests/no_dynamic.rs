@@ -0,0 +1,241 @@
+use allow_pin::fake_kernel::{self, Shared, Syscall};
+use allow_pin::no_dynamic::*;
+use allow_pin::{DriverNum, DynamicType, ErrorCode};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[test]
+fn allow_then_drop() {
+    fake_kernel::reset();
+    {
+        let mut buffer = pin!(Buffer::<AllowRw<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+        let address = buffer.as_mut().buffer_mut().as_mut_ptr();
+        fake_kernel::take_log();
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        assert_eq!(
+            fake_kernel::shared(DynamicType::Rw, 1, 2),
+            Some(Shared { address, len: 4 })
+        );
+        assert_eq!(
+            fake_kernel::take_log(),
+            [Syscall::Allow {
+                allow_type: DynamicType::Rw,
+                driver_num: 1,
+                buffer_num: 2,
+                address,
+                len: 4,
+            }]
+        );
+    }
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 2), None);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Rw, 1, 2)]
+    );
+}
+
+#[test]
+fn rw_buffer_unallows() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowRw<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+    assert_eq!(buffer.as_mut().allow(), Ok(()));
+    fake_kernel::take_log();
+    assert_eq!(*buffer.as_ref().buffer(), [0; 4]);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Rw, 1, 2)]
+    );
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 2), None);
+}
+
+#[test]
+fn ro_buffer_stays_shared() {
+    fake_kernel::reset();
+    let buffer = pin!(Buffer::<AllowRo<DriverNum<1>, 1>, [u8; 2]>::from(*b"hi"));
+    assert_eq!(buffer.as_ref().allow_ro(), Ok(()));
+    assert_eq!(buffer.as_ref().buffer(), b"hi");
+    let log = fake_kernel::take_log();
+    assert_eq!(log.len(), 1);
+    assert!(!log[0].is_unallow());
+    assert!(fake_kernel::shared(DynamicType::Ro, 1, 1).is_some());
+}
+
+#[cfg(not(feature = "registry"))]
+#[test]
+fn never_shared_buffer_unallows_on_drop() {
+    fake_kernel::reset();
+    drop(Buffer::<AllowRo<DriverNum<1>, 1>, [u8; 2]>::from(*b"hi"));
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Ro, 1, 1)]
+    );
+}
+
+#[test]
+fn replace_with_mut() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([1; 2]));
+    let mut b = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([2; 2]));
+    let b_address = b.as_mut().buffer_mut().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    fake_kernel::take_log();
+    let (old, result) = a.as_mut().replace_with_mut(b.as_mut());
+    assert_eq!(result, Ok(()));
+    assert_eq!(*old, [1; 2]);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 0),
+        Some(Shared {
+            address: b_address,
+            len: 2
+        })
+    );
+    assert_eq!(fake_kernel::take_log().len(), 1);
+}
+
+#[test]
+fn failed_replace_with_mut_unallows() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([1; 2]));
+    let mut b = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([2; 2]));
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    let (old, result) = a.as_mut().replace_with_mut(b.as_mut());
+    assert_eq!(result, Err(ErrorCode::NoMem));
+    assert_eq!(*old, [1; 2]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn failed_allow() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowRw<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+    fake_kernel::fail_next(ErrorCode::NoDevice);
+    assert_eq!(buffer.as_mut().allow(), Err(ErrorCode::NoDevice));
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn subscription() {
+    fake_kernel::reset();
+    {
+        let done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+        assert_eq!(done.as_ref().subscribe(), Ok(()));
+        assert!(fake_kernel::subscribed(1, 1));
+        assert!(fake_kernel::upcall(1, 1, [1, 2, 3]));
+        assert_eq!(done.as_ref().upcall().get(), Some([1, 2, 3]));
+        let log = fake_kernel::take_log();
+        assert_eq!(log.len(), 1);
+        assert!(!log[0].is_unsubscribe());
+    }
+    assert!(!fake_kernel::subscribed(1, 1));
+    assert_eq!(fake_kernel::take_log(), [Syscall::unsubscribe(1, 1)]);
+}
+
+#[test]
+fn userspace_readable_reads_without_unallowing() {
+    fake_kernel::reset();
+    let mut counter =
+        pin!(Buffer::<AllowUserspaceReadable<DriverNum<2>, 0>, [u8; 4]>::from([0; 4]));
+    assert_eq!(counter.as_mut().allow(), Ok(()));
+    assert!(fake_kernel::write(
+        DynamicType::UserspaceReadable,
+        2,
+        0,
+        0,
+        &7u32.to_ne_bytes()
+    ));
+    assert_eq!(counter.as_mut().read().map(u32::from_ne_bytes), Some(7));
+    let mut high = [0; 2];
+    counter.as_mut().read_volatile(2, &mut high);
+    assert_eq!(high, [7u32.to_ne_bytes()[2], 7u32.to_ne_bytes()[3]]);
+    assert_eq!(fake_kernel::take_log().len(), 1);
+    assert!(fake_kernel::shared(DynamicType::UserspaceReadable, 2, 0).is_some());
+    assert_eq!(counter.as_ref().buffer(), &7u32.to_ne_bytes());
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::UserspaceReadable, 2, 0)]
+    );
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn registry_skips_unallow_when_not_holder() {
+    fake_kernel::reset();
+    drop(Buffer::<AllowRo<DriverNum<1>, 1>, [u8; 2]>::from(*b"hi"));
+    assert_eq!(fake_kernel::take_log(), []);
+
+    let mut a = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([1; 2]));
+    let mut b = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([2; 2]));
+    let b_address = b.as_mut().buffer_mut().as_mut_ptr();
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    let (_, result) = a.as_mut().replace_with_mut(b.as_mut());
+    assert_eq!(result, Ok(()));
+    fake_kernel::take_log();
+    // `b` now holds the allow ID, so reading `a` does not unallow it.
+    assert_eq!(*a.as_ref().buffer(), [1; 2]);
+    assert_eq!(fake_kernel::take_log(), []);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 0),
+        Some(Shared {
+            address: b_address,
+            len: 2
+        })
+    );
+    assert_eq!(*b.as_ref().buffer(), [2; 2]);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Rw, 1, 0)]
+    );
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn registry_overflow_falls_back_to_unconditional_unallow() {
+    fake_kernel::reset();
+    // The registry tracks 8 allow IDs; sharing a 9th overflows it.
+    macro_rules! allow_each {
+        ($($buffer_num:literal),*) => {
+            $(
+                let mut buffer = pin!(Buffer::<AllowRo<DriverNum<3>, $buffer_num>, [u8; 1]>::from([0]));
+                assert_eq!(buffer.as_mut().allow(), Ok(()));
+            )*
+        };
+    }
+    allow_each!(0, 1, 2, 3, 4, 5, 6, 7, 8);
+    fake_kernel::take_log();
+    drop(Buffer::<AllowRo<DriverNum<3>, 9>, [u8; 1]>::from([0]));
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Ro, 3, 9)]
+    );
+}
+
+#[test]
+fn borrowed_buffer_unallows_when_scope_ends() {
+    fake_kernel::reset();
+    let mut data = [0; 4];
+    let address = data.as_mut_ptr();
+    BorrowedBuffer::<AllowRw<DriverNum<1>, 2>>::with_mut(&mut data, |mut buffer| {
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        assert_eq!(
+            fake_kernel::shared(DynamicType::Rw, 1, 2),
+            Some(Shared { address, len: 4 })
+        );
+        assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, &[5]));
+        fake_kernel::take_log();
+    });
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Rw, 1, 2)]
+    );
+    assert_eq!(data, [5, 0, 0, 0]);
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn read_volatile_rejects_an_overflowing_offset() {
+    fake_kernel::reset();
+    let mut counter =
+        pin!(Buffer::<AllowUserspaceReadable<DriverNum<2>, 0>, [u8; 4]>::from([0; 4]));
+    counter.as_mut().read_volatile(usize::MAX, &mut [0; 2]);
+}
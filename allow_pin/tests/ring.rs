@@ -0,0 +1,63 @@
+use allow_pin::fake_kernel::{self, Shared};
+use allow_pin::ring::BufferRing;
+use allow_pin::{AllowRw, DriverNum, DynamicType, ErrorCode};
+use core::pin::pin;
+
+fn shared_address() -> *mut u8 {
+    let Some(Shared { address, .. }) = fake_kernel::shared(DynamicType::Rw, 1, 0) else {
+        panic!("a ring buffer should be shared");
+    };
+    address
+}
+
+#[test]
+fn rotate_and_consume_in_order() {
+    fake_kernel::reset();
+    let mut ring = pin!(BufferRing::<AllowRw<DriverNum<1>, 0>, [u8; 2], 3>::default());
+    assert_eq!(ring.as_mut().rotate(), Err(ErrorCode::Off));
+    assert_eq!(ring.as_mut().consume(|_| ()), None);
+    assert_eq!(ring.as_mut().start(), Ok(()));
+    assert_eq!(ring.as_mut().start(), Err(ErrorCode::Already));
+
+    let mut addresses = vec![shared_address()];
+    for value in 1..=2 {
+        assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &[value]));
+        assert_eq!(ring.as_mut().rotate(), Ok(()));
+        addresses.push(shared_address());
+    }
+    assert_eq!(ring.as_ref().filled(), 2);
+    addresses.sort();
+    addresses.dedup();
+    assert_eq!(addresses.len(), 3);
+
+    // Every other buffer is filled, so the shared one cannot be swapped out.
+    assert_eq!(ring.as_mut().rotate(), Err(ErrorCode::Busy));
+    assert_eq!(ring.as_mut().consume(|buffer| *buffer), Some([1, 0]));
+    assert_eq!(ring.as_ref().filled(), 1);
+
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &[3]));
+    assert_eq!(ring.as_mut().rotate(), Ok(()));
+    assert_eq!(ring.as_mut().consume(|buffer| *buffer), Some([2, 0]));
+    assert_eq!(ring.as_mut().consume(|buffer| *buffer), Some([3, 0]));
+    assert_eq!(ring.as_mut().consume(|buffer| *buffer), None);
+}
+
+#[test]
+fn failed_rotate_shares_the_current_buffer_again() {
+    fake_kernel::reset();
+    let mut ring = pin!(BufferRing::<AllowRw<DriverNum<1>, 0>, [u8; 2], 2>::default());
+    assert_eq!(ring.as_mut().start(), Ok(()));
+    let current = shared_address();
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &[1]));
+
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    assert_eq!(ring.as_mut().rotate(), Err(ErrorCode::NoMem));
+    assert_eq!(shared_address(), current);
+    assert_eq!(ring.as_ref().filled(), 0);
+
+    // The kernel keeps writing to the same buffer, and nothing is lost.
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 1, &[2]));
+    assert_eq!(ring.as_mut().rotate(), Ok(()));
+    assert_ne!(shared_address(), current);
+    assert_eq!(ring.as_mut().consume(|buffer| *buffer), Some([1, 2]));
+}
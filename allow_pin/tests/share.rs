@@ -0,0 +1,84 @@
+use allow_pin::fake_kernel::{self, Shared, Syscall};
+use allow_pin::share::*;
+use allow_pin::{DriverNum, DynamicType, ErrorCode};
+use core::cell::Cell;
+
+#[test]
+fn scope_clears_every_id() {
+    fake_kernel::reset();
+    let ro_buffer = *b"hi";
+    let mut rw_buffer = [0u8; 4];
+    let done: Cell<Option<[u32; 3]>> = Cell::new(None);
+    let rw_address = rw_buffer.as_mut_ptr();
+    let result = scope::<
+        (
+            AllowRo<DriverNum<1>, 0>,
+            AllowRw<DriverNum<1>, 0>,
+            Subscribe<1, 0>,
+        ),
+        _,
+        _,
+    >(|handle| {
+        let (allow_ro, allow_rw, subscribe) = handle.split();
+        allow_ro.allow(&ro_buffer)?;
+        allow_rw.allow(&mut rw_buffer)?;
+        subscribe.subscribe(&done)?;
+        assert_eq!(
+            fake_kernel::shared(DynamicType::Rw, 1, 0),
+            Some(Shared {
+                address: rw_address,
+                len: 4
+            })
+        );
+        assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &[1, 2]));
+        assert!(fake_kernel::upcall(1, 0, [2, 0, 0]));
+        Ok::<_, u32>(())
+    });
+    assert_eq!(result, Ok(()));
+    assert_eq!(rw_buffer, [1, 2, 0, 0]);
+    assert_eq!(done.get(), Some([2, 0, 0]));
+    assert_eq!(fake_kernel::allow_table(), []);
+    assert!(!fake_kernel::subscribed(1, 0));
+    assert_eq!(
+        fake_kernel::take_log()[3..],
+        [
+            Syscall::unallow(DynamicType::Ro, 1, 0),
+            Syscall::unallow(DynamicType::Rw, 1, 0),
+            Syscall::unsubscribe(1, 0),
+        ]
+    );
+}
+
+#[test]
+fn unused_ids_are_still_cleared() {
+    fake_kernel::reset();
+    scope::<AllowRw<DriverNum<2>, 1>, _, _>(|_| {});
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Rw, 2, 1)]
+    );
+}
+
+#[test]
+fn failed_allow_is_cleared() {
+    fake_kernel::reset();
+    let buffer = [0u8; 4];
+    fake_kernel::fail_next(ErrorCode::NoDevice);
+    let result = scope::<AllowRo<DriverNum<2>, 0>, _, _>(|handle| handle.allow(&buffer));
+    assert_eq!(result, Err(ErrorCode::NoDevice));
+    assert_eq!(fake_kernel::take_log().len(), 2);
+}
+
+#[test]
+fn scope_clears_ids_on_unwind() {
+    fake_kernel::reset();
+    let mut buffer = [0u8; 4];
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        scope::<AllowRw<DriverNum<3>, 0>, _, _>(|handle| {
+            handle.allow(&mut buffer).unwrap();
+            panic!("unwinding out of the scope");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
@@ -0,0 +1,461 @@
+//! Checks that no public method of `no_dynamic`, `dynamic_type`,
+//! `full_dynamic` or `uninit` hands out a reference to a buffer the kernel can still
+//! access. Every reference a method returns is passed to `check` or
+//! `check_mut`, which fail if the reference overlaps a shared buffer and then
+//! let the fake kernel write to every buffer it holds. Run these under Miri
+//! (`make miri`) as well: Miri reports a kernel write through a pointer that an
+//! app reference has invalidated, even when the values happen to match.
+
+use allow_pin::fake_kernel::{self, SCRIBBLE};
+use allow_pin::{
+    Allow, AllowRo, AllowRw, AllowSet, AllowUserspaceReadable, DriverNum, DynamicType, ErrorCode,
+};
+use allow_pin::{dynamic_type, full_dynamic, no_dynamic, uninit};
+use core::cell::Cell;
+use core::pin::pin;
+
+type Ro = AllowRo<DriverNum<1>, 2>;
+type Rw = AllowRw<DriverNum<1>, 2>;
+type Ura = AllowUserspaceReadable<DriverNum<1>, 3>;
+// The same allow numbers without a class, for `dynamic_type`.
+type Num = Allow<DriverNum<1>, 2>;
+type UraNum = Allow<DriverNum<1>, 3>;
+
+// True if `bytes` overlaps a buffer the kernel holds. Read-only buffers only
+// count if `including_ro` is set, as the app may read them while shared.
+fn kernel_holds(bytes: &[u8], including_ro: bool) -> bool {
+    let start = bytes.as_ptr().addr();
+    let end = start + bytes.len();
+    fake_kernel::allow_table()
+        .into_iter()
+        .any(|(_, _, allow_type, shared)| {
+            (including_ro || allow_type != DynamicType::Ro)
+                && shared.address.addr() < end
+                && start < shared.address.addr() + shared.len
+        })
+}
+
+// Checks a shared reference the app was given: the kernel must not be able to
+// write to it, so its contents survive a scribble.
+fn check(bytes: &[u8]) {
+    assert!(!kernel_holds(bytes, false), "reference to a shared buffer");
+    let before = bytes.to_vec();
+    fake_kernel::scribble();
+    assert_eq!(bytes, before);
+}
+
+// Checks a mutable reference the app was given: the kernel must not hold it at
+// all, and the app can still write through it after a scribble.
+fn check_mut(bytes: &mut [u8]) {
+    assert!(
+        !kernel_holds(bytes, true),
+        "mutable reference to a shared buffer"
+    );
+    let before = bytes.to_vec();
+    fake_kernel::scribble();
+    assert_eq!(bytes, before);
+    bytes.fill(!SCRIBBLE);
+    bytes.copy_from_slice(&before);
+}
+
+#[test]
+fn no_dynamic_ro() {
+    fake_kernel::reset();
+    let mut a = pin!(no_dynamic::Buffer::<Ro, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(no_dynamic::Buffer::<Ro, [u8; 4]>::from([2; 4]));
+    assert_eq!(a.as_ref().allow_ro(), Ok(()));
+    check(a.as_ref().buffer());
+    let (old, result) = a.as_ref().replace_with_ro(b.as_ref());
+    assert_eq!(result, Ok(()));
+    check(old);
+    check_mut(b.as_mut().buffer_mut());
+    assert_eq!(b.as_mut().allow(), Ok(()));
+    let (old, result) = b.as_mut().replace_with_mut_ro(a.as_ref());
+    assert_eq!(result, Ok(()));
+    check_mut(old);
+    let (old, result) = a.as_ref().replace_with(b.as_mut());
+    assert_eq!(result, Ok(()));
+    check(old);
+    let (old, result) = b.as_mut().replace_with_mut(a.as_mut());
+    assert_eq!(result, Ok(()));
+    check_mut(old);
+    check_mut(a.as_mut().buffer_mut());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn no_dynamic_rw() {
+    fake_kernel::reset();
+    let mut a = pin!(no_dynamic::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(no_dynamic::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    assert_eq!(fake_kernel::scribble(), 1);
+    check(a.as_ref().buffer());
+    assert_eq!(*a.as_ref().buffer(), [SCRIBBLE; 4]);
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    check_mut(a.as_mut().buffer_mut());
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    let (old, result) = a.as_ref().replace_with(b.as_mut());
+    assert_eq!(result, Ok(()));
+    check(old);
+    let (old, result) = b.as_mut().replace_with_mut(a.as_mut());
+    assert_eq!(result, Ok(()));
+    check_mut(old);
+    check_mut(a.as_mut().buffer_mut());
+    assert_eq!(*b.as_mut().buffer_mut(), [SCRIBBLE; 4]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+// A failed replace leaves the old buffer shared unless the replace unallows it.
+#[test]
+fn no_dynamic_failed_replace() {
+    fake_kernel::reset();
+    let mut a = pin!(no_dynamic::Buffer::<Rw, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(no_dynamic::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    let (old, result) = a.as_ref().replace_with(b.as_mut());
+    assert_eq!(result, Err(ErrorCode::NoMem));
+    check(old);
+    assert_eq!(a.as_mut().allow(), Ok(()));
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    let (old, result) = a.as_mut().replace_with_mut(b.as_mut());
+    assert_eq!(result, Err(ErrorCode::NoMem));
+    check_mut(old);
+    assert_eq!(fake_kernel::allow_table(), []);
+
+    let c = pin!(no_dynamic::Buffer::<Ro, [u8; 4]>::from([3; 4]));
+    let mut d = pin!(no_dynamic::Buffer::<Ro, [u8; 4]>::from([4; 4]));
+    assert_eq!(d.as_mut().allow(), Ok(()));
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    let (old, result) = d.as_mut().replace_with_mut_ro(c.as_ref());
+    assert_eq!(result, Err(ErrorCode::NoMem));
+    check_mut(old);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn no_dynamic_userspace_readable() {
+    fake_kernel::reset();
+    let mut buffer = pin!(no_dynamic::Buffer::<Ura, [u8; 4]>::from([1; 4]));
+    assert_eq!(buffer.as_mut().allow(), Ok(()));
+    assert_eq!(fake_kernel::scribble(), 1);
+    let mut dest = [0; 2];
+    buffer.as_mut().read_volatile(1, &mut dest);
+    assert_eq!(dest, [SCRIBBLE; 2]);
+    assert_eq!(buffer.as_mut().read(), Some([SCRIBBLE; 4]));
+    check(buffer.as_ref().buffer());
+    assert_eq!(buffer.as_mut().allow(), Ok(()));
+    check_mut(buffer.as_mut().buffer_mut());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn no_dynamic_borrowed() {
+    fake_kernel::reset();
+    let data = [1u8; 4];
+    no_dynamic::BorrowedBuffer::<Ro>::with(&data, |mut buffer| {
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        check(buffer.as_ref().buffer());
+    });
+    let mut data = [2u8; 4];
+    no_dynamic::BorrowedBuffer::<Rw>::with_mut(&mut data, |mut buffer| {
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        check(buffer.as_ref().buffer());
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        fake_kernel::scribble();
+        check_mut(buffer.as_mut().buffer_mut());
+    });
+    assert_eq!(data, [SCRIBBLE; 4]);
+    no_dynamic::BorrowedBuffer::<Ura>::with_mut(&mut data, |mut buffer| {
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        check(buffer.as_ref().buffer());
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        check_mut(buffer.as_mut().buffer_mut());
+        // Left shared, for the end of the scope to unallow.
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+    });
+    assert_eq!(fake_kernel::allow_table(), []);
+    check_mut(&mut data);
+}
+
+#[test]
+fn no_dynamic_subscription() {
+    fake_kernel::reset();
+    let done = pin!(no_dynamic::Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    let upcall = done.as_ref().upcall();
+    assert!(fake_kernel::upcall(1, 1, [1, 2, 3]));
+    assert_eq!(upcall.get(), Some([1, 2, 3]));
+    done.as_ref().unsubscribe();
+    assert!(!fake_kernel::upcall(1, 1, [4, 5, 6]));
+}
+
+#[test]
+fn dynamic_type_buffer() {
+    fake_kernel::reset();
+    let mut buffer = pin!(dynamic_type::Buffer::<Num, [u8; 4]>::from([1; 4]));
+    check_mut(buffer.as_mut().buffer_mut().unwrap());
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw), Ok(()));
+    assert_eq!(buffer.as_ref().share_status(), Some(DynamicType::Rw));
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+    assert_eq!(buffer.as_mut().read_volatile(0, &mut [0; 4]), None);
+    assert_eq!(buffer.as_mut().read(), None);
+    fake_kernel::scribble();
+    check_mut(buffer.as_mut().unallow());
+    check(buffer.as_ref().buffer().unwrap());
+    assert_eq!(buffer.as_mut().read(), Some([SCRIBBLE; 4]));
+
+    let mut buffer = pin!(dynamic_type::Buffer::<UraNum, [u8; 4]>::from([1; 4]));
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::UserspaceReadable),
+        Ok(())
+    );
+    fake_kernel::scribble();
+    let mut dest = [0; 2];
+    assert_eq!(buffer.as_mut().read_volatile(2, &mut dest), Some(()));
+    assert_eq!(dest, [SCRIBBLE; 2]);
+    assert_eq!(buffer.as_mut().read(), Some([SCRIBBLE; 4]));
+    check_mut(buffer.as_mut().unallow());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn dynamic_type_foreign_buffer_is_not_handed_out() {
+    fake_kernel::reset();
+    let mut a = pin!(dynamic_type::Buffer::<Num, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(dynamic_type::Buffer::<Num, [u8; 4]>::from([2; 4]));
+    assert_eq!(a.as_mut().allow(DynamicType::Rw), Ok(()));
+    assert_eq!(
+        b.as_mut().allow(DynamicType::Rw),
+        Err(ErrorCode::ForeignBufferSwappedOut)
+    );
+    check_mut(b.as_mut().buffer_mut().unwrap());
+    assert_eq!(a.as_mut().buffer_mut(), None);
+    check_mut(a.as_mut().unallow());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn dynamic_type_replace_with() {
+    fake_kernel::reset();
+    let mut a = pin!(dynamic_type::Buffer::<Num, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(dynamic_type::Buffer::<Num, [u8; 4]>::from([2; 4]));
+    assert_eq!(a.as_mut().allow(DynamicType::Rw), Ok(()));
+    let (old, result) = a.as_mut().replace_with(b.as_mut());
+    assert_eq!(result, Ok(()));
+    check_mut(old);
+    assert_eq!(b.as_ref().share_status(), Some(DynamicType::Rw));
+    check_mut(b.as_mut().unallow());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn dynamic_type_allow_set() {
+    fake_kernel::reset();
+    let mut a = pin!(dynamic_type::Buffer::<Ro, [u8; 4]>::from([1; 4]));
+    let mut b = pin!(dynamic_type::Buffer::<Rw, [u8; 4]>::from([2; 4]));
+    let set = AllowSet::allow((a.as_mut(), b.as_mut())).ok().unwrap();
+    fake_kernel::scribble();
+    let (mut a, mut b) = set.release();
+    check_mut(a.as_mut().buffer_mut().unwrap());
+    check_mut(b.as_mut().buffer_mut().unwrap());
+    assert_eq!(*b.as_mut().buffer_mut().unwrap(), [SCRIBBLE; 4]);
+    drop(AllowSet::allow((a.as_mut(), b.as_mut())).ok().unwrap());
+    check_mut(b.as_mut().buffer_mut().unwrap());
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    let Err(((_, mut b), error)) = AllowSet::allow((a.as_mut(), b.as_mut())) else {
+        panic!("allow should fail");
+    };
+    assert_eq!(error, ErrorCode::NoMem);
+    check_mut(b.as_mut().buffer_mut().unwrap());
+    check_mut(a.as_mut().buffer_mut().unwrap());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn dynamic_type_borrowed() {
+    fake_kernel::reset();
+    let data = [1u8; 4];
+    dynamic_type::BorrowedBuffer::<Num>::with(&data, |mut buffer| {
+        check(buffer.as_ref().buffer().unwrap());
+        assert_eq!(buffer.as_mut().allow(DynamicType::Ro), Ok(()));
+        assert_eq!(buffer.as_ref().share_status(), Some(DynamicType::Ro));
+        assert_eq!(buffer.as_ref().buffer(), None);
+        check(buffer.as_mut().unallow());
+    });
+    let mut data = [2u8; 4];
+    dynamic_type::BorrowedBuffer::<Num>::with_mut(&mut data, |mut buffer| {
+        assert_eq!(buffer.as_mut().allow(DynamicType::Rw), Ok(()));
+        assert_eq!(buffer.as_mut().buffer_mut(), None);
+        fake_kernel::scribble();
+        check(buffer.as_mut().unallow());
+        check_mut(buffer.as_mut().buffer_mut().unwrap());
+        assert_eq!(buffer.as_mut().allow(DynamicType::Rw), Ok(()));
+    });
+    assert_eq!(data, [SCRIBBLE; 4]);
+    dynamic_type::BorrowedBuffer::<UraNum>::with_mut(&mut data, |mut buffer| {
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::UserspaceReadable),
+            Ok(())
+        );
+        assert_eq!(buffer.as_mut().buffer_mut(), None);
+        check(buffer.as_mut().unallow());
+        check_mut(buffer.as_mut().buffer_mut().unwrap());
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::UserspaceReadable),
+            Ok(())
+        );
+    });
+    assert_eq!(fake_kernel::allow_table(), []);
+    check_mut(&mut data);
+}
+
+#[test]
+fn dynamic_type_subscription() {
+    fake_kernel::reset();
+    let mut done = pin!(dynamic_type::Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+    assert_eq!(done.as_mut().subscribe(), Ok(()));
+    assert!(done.as_ref().is_subscribed());
+    let upcall = done.as_ref().upcall();
+    assert!(fake_kernel::upcall(1, 1, [1, 2, 3]));
+    assert_eq!(upcall.get(), Some([1, 2, 3]));
+    done.as_mut().unsubscribe();
+    assert!(!fake_kernel::upcall(1, 1, [4, 5, 6]));
+}
+
+#[test]
+fn full_dynamic_buffer() {
+    fake_kernel::reset();
+    let mut buffer = pin!(full_dynamic::Buffer::from([1u8; 4]));
+    check_mut(buffer.as_mut().buffer_mut().unwrap());
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+    assert_eq!(buffer.as_mut().read_volatile(0, &mut [0; 4]), None);
+    assert_eq!(buffer.as_mut().read(), None);
+    fake_kernel::scribble();
+    check_mut(buffer.as_mut().unallow());
+    check(buffer.as_ref().buffer().unwrap());
+
+    assert_eq!(buffer.as_mut().allow_slot::<Ura>(), Ok(()));
+    fake_kernel::scribble();
+    let mut dest = [0; 2];
+    assert_eq!(buffer.as_mut().read_volatile(2, &mut dest), Some(()));
+    assert_eq!(buffer.as_mut().read(), Some([SCRIBBLE; 4]));
+    check_mut(buffer.as_mut().unallow());
+
+    assert_eq!(
+        buffer.as_mut().allow_range(1..3, DynamicType::Rw, 1, 2),
+        Ok(())
+    );
+    assert_eq!(buffer.as_ref().buffer(), None);
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+    check_mut(buffer.as_mut().unallow());
+
+    let mut other = pin!(full_dynamic::Buffer::from([2u8; 4]));
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+    assert_eq!(
+        other.as_mut().allow(DynamicType::Rw, 1, 2),
+        Err(ErrorCode::ForeignBufferSwappedOut)
+    );
+    check_mut(other.as_mut().buffer_mut().unwrap());
+    check_mut(buffer.as_mut().unallow());
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn full_dynamic_static_buffer() {
+    fake_kernel::reset();
+    let ro: &'static [u8; 4] = Box::leak(Box::new([1; 4]));
+    let mut buffer = pin!(full_dynamic::StaticBuffer::from(ro));
+    assert_eq!(buffer.as_mut().allow(1, 2), Ok(()));
+    assert_eq!(buffer.as_ref().buffer(), None);
+    check(buffer.as_mut().unallow().unwrap());
+    check(buffer.as_ref().buffer().unwrap());
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+
+    let rw: &'static mut [u8; 4] = Box::leak(Box::new([2; 4]));
+    let mut buffer = pin!(full_dynamic::StaticBuffer::from(rw));
+    assert_eq!(buffer.as_mut().allow(1, 2), Ok(()));
+    assert_eq!(buffer.as_mut().buffer_mut(), None);
+    fake_kernel::scribble();
+    check(buffer.as_mut().unallow().unwrap());
+    check_mut(buffer.as_mut().buffer_mut().unwrap());
+    assert_eq!(buffer.as_mut().allow(1, 2), Ok(()));
+    let Some(full_dynamic::StaticRef::Rw(rw)) = buffer.as_mut().take() else {
+        panic!("the buffer was created from a mutable reference");
+    };
+    check_mut(rw);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn full_dynamic_borrowed() {
+    fake_kernel::reset();
+    let data = [1u8; 4];
+    full_dynamic::BorrowedBuffer::with(&data, |mut buffer| {
+        assert_eq!(
+            buffer.as_mut().allow(DynamicType::Rw, 1, 2),
+            Err(ErrorCode::Invalid)
+        );
+        assert_eq!(buffer.as_mut().allow_slot::<Ro>(), Ok(()));
+        assert_eq!(buffer.as_ref().buffer(), None);
+        check(buffer.as_mut().unallow());
+        check(buffer.as_ref().buffer().unwrap());
+        assert_eq!(buffer.as_mut().buffer_mut(), None);
+    });
+    let mut data = [2u8; 4];
+    full_dynamic::BorrowedBuffer::with_mut(&mut data, |mut buffer| {
+        assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+        assert_eq!(buffer.as_mut().buffer_mut(), None);
+        fake_kernel::scribble();
+        check(buffer.as_mut().unallow());
+        check_mut(buffer.as_mut().buffer_mut().unwrap());
+        assert_eq!(buffer.as_mut().allow_slot::<Ura>(), Ok(()));
+    });
+    assert_eq!(fake_kernel::allow_table(), []);
+    check_mut(&mut data);
+}
+
+#[test]
+fn full_dynamic_subscription() {
+    fake_kernel::reset();
+    let mut done = pin!(full_dynamic::Subscription::<Cell<Option<[u32; 3]>>>::default());
+    assert_eq!(done.as_mut().subscribe(1, 1), Ok(()));
+    let upcall = done.as_ref().upcall();
+    assert!(fake_kernel::upcall(1, 1, [1, 2, 3]));
+    assert_eq!(upcall.get(), Some([1, 2, 3]));
+    done.as_mut().unsubscribe();
+    assert!(!fake_kernel::upcall(1, 1, [4, 5, 6]));
+}
+
+#[test]
+fn uninit_buffer() {
+    fake_kernel::reset();
+    let mut a = pin!(uninit::Buffer::<Rw, 4>::default());
+    let mut b = pin!(uninit::Buffer::<Rw, 4>::default());
+    let done = pin!(uninit::Subscription::<DriverNum<1>, 0, 1>::default());
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    assert_eq!(a.as_mut().allow(done.as_ref()), Ok(()));
+    let shared = fake_kernel::shared(DynamicType::Rw, 1, 2).unwrap();
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, &[7; 2]));
+    assert!(fake_kernel::upcall(1, 0, [0, 2, 0]));
+    // The bytes past the reported length are left untouched.
+    let rest = unsafe { core::slice::from_raw_parts(shared.address.add(2), 2) };
+    assert_eq!(*rest, [uninit::POISON; 2]);
+    let prefix = a.as_mut().unallow(done.as_ref());
+    assert_eq!(prefix, [7; 2]);
+    check_mut(prefix);
+    // `b` swaps `a` out, so a report received after that describes `b`.
+    assert_eq!(a.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(b.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(fake_kernel::scribble(), 1);
+    assert!(fake_kernel::upcall(1, 0, [0, 4, 0]));
+    let data = b.as_mut().unallow(done.as_ref());
+    assert_eq!(data, [SCRIBBLE; 4]);
+    check_mut(data);
+    assert_eq!(a.as_mut().unallow(done.as_ref()), []);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
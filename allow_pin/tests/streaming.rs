@@ -0,0 +1,104 @@
+use allow_pin::fake_kernel::{self, Shared};
+use allow_pin::streaming::*;
+use allow_pin::{AllowRw, DriverNum, DynamicType, ErrorCode};
+use core::pin::pin;
+
+// A header as the kernel writes it.
+fn header(flags: u16, write_offset: u32) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[0..2].copy_from_slice(&VERSION.to_ne_bytes());
+    header[2..4].copy_from_slice(&flags.to_ne_bytes());
+    header[4..8].copy_from_slice(&write_offset.to_ne_bytes());
+    header
+}
+
+#[test]
+fn parse_returns_written_bytes() {
+    let mut buffer = [0; 12];
+    buffer[..HEADER_LEN].copy_from_slice(&header(0, 2));
+    buffer[HEADER_LEN..].copy_from_slice(&[1, 2, 3, 4]);
+    let received = parse(&mut buffer).unwrap();
+    assert_eq!(received.data, [1, 2]);
+    assert!(!received.exceeded);
+    assert_eq!(buffer[..HEADER_LEN], [0; HEADER_LEN]);
+}
+
+#[test]
+fn parse_rejects_bad_headers() {
+    assert_eq!(parse(&mut [0; 4]), Err(ErrorCode::Size));
+    let mut buffer = [0; 12];
+    buffer[..HEADER_LEN].copy_from_slice(&header(0, 5));
+    assert_eq!(parse(&mut buffer), Err(ErrorCode::Size));
+    buffer[..HEADER_LEN].copy_from_slice(&header(0, 0));
+    buffer[..2].copy_from_slice(&1u16.to_ne_bytes());
+    assert_eq!(parse(&mut buffer), Err(ErrorCode::NoSupport));
+}
+
+#[test]
+fn receive_swaps_buffers() {
+    fake_kernel::reset();
+    let mut stream = pin!(StreamingReceiveSlice::<AllowRw<DriverNum<1>, 0>, 12>::default());
+    assert_eq!(stream.as_mut().next(), Err(ErrorCode::Off));
+    assert_eq!(stream.as_mut().start(), Ok(()));
+    assert_eq!(stream.as_mut().start(), Err(ErrorCode::Already));
+    let Some(Shared { address: a, .. }) = fake_kernel::shared(DynamicType::Rw, 1, 0) else {
+        panic!("start should share a buffer");
+    };
+
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &header(0, 3)));
+    assert!(fake_kernel::write(
+        DynamicType::Rw,
+        1,
+        0,
+        HEADER_LEN,
+        &[7, 8, 9]
+    ));
+    let received = stream.as_mut().next().unwrap();
+    assert_eq!(received.data, [7, 8, 9]);
+    assert!(!received.exceeded);
+    let Some(Shared { address: b, len }) = fake_kernel::shared(DynamicType::Rw, 1, 0) else {
+        panic!("next should share the other buffer");
+    };
+    assert_ne!(a, b);
+    assert_eq!(len, 12);
+
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &header(1, 4)));
+    let received = stream.as_mut().next().unwrap();
+    assert_eq!(received.data, [0; 4]);
+    assert!(received.exceeded);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 0).map(|shared| shared.address),
+        Some(a)
+    );
+
+    // The first buffer's header was zeroed when it was swapped out.
+    let received = stream.as_mut().next().unwrap();
+    assert_eq!(received.data, []);
+    assert!(!received.exceeded);
+}
+
+#[test]
+fn failed_swap_shares_the_current_buffer_again() {
+    fake_kernel::reset();
+    let mut stream = pin!(StreamingReceiveSlice::<AllowRw<DriverNum<1>, 0>, 12>::default());
+    assert_eq!(stream.as_mut().start(), Ok(()));
+    let a = fake_kernel::shared(DynamicType::Rw, 1, 0);
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &header(0, 1)));
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, HEADER_LEN, &[7]));
+
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    assert_eq!(stream.as_mut().next(), Err(ErrorCode::NoMem));
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 0), a);
+
+    // The kernel keeps appending to the same buffer, and nothing is lost.
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &header(0, 2)));
+    assert!(fake_kernel::write(
+        DynamicType::Rw,
+        1,
+        0,
+        HEADER_LEN + 1,
+        &[8]
+    ));
+    assert_eq!(stream.as_mut().next().unwrap().data, [7, 8]);
+    assert_ne!(fake_kernel::shared(DynamicType::Rw, 1, 0), a);
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "trace")]
+
+use allow_pin::trace::{self, Entry, Kind};
+use allow_pin::{AllowRw, DriverNum, DynamicType, command, fake_kernel};
+use allow_pin::{full_dynamic, no_dynamic};
+use core::pin::pin;
+use core::ptr::null_mut;
+
+type Rw = AllowRw<DriverNum<1>, 2>;
+
+fn entries() -> Vec<Entry> {
+    let mut entries = Vec::new();
+    assert_eq!(trace::dump(|entry| entries.push(*entry)), 0);
+    entries
+}
+
+#[test]
+fn records_allows_unshares_and_commands() {
+    fake_kernel::reset();
+    let mut buffer = pin!(full_dynamic::Buffer::from([0u8; 4]));
+    let address = buffer.as_mut().buffer_mut().unwrap().as_mut_ptr();
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+    assert_eq!(command(1, 3, 0, 0), Ok(()));
+    buffer.as_mut().unallow();
+    assert_eq!(
+        entries(),
+        [
+            Entry {
+                kind: Kind::DynamicAllow,
+                driver_num: 1,
+                buffer_num: 2,
+                class: Some(DynamicType::Rw),
+                address,
+                len: 4,
+            },
+            Entry {
+                kind: Kind::Command,
+                driver_num: 1,
+                buffer_num: 3,
+                class: None,
+                address: null_mut(),
+                len: 0,
+            },
+            Entry {
+                kind: Kind::Unshare,
+                driver_num: 1,
+                buffer_num: 2,
+                class: Some(DynamicType::Rw),
+                address: null_mut(),
+                len: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn keeps_the_most_recent_calls() {
+    fake_kernel::reset();
+    for command_num in 0..trace::LEN as u32 + 3 {
+        assert_eq!(command(1, command_num, 0, 0), Ok(()));
+    }
+    let mut entries = Vec::new();
+    assert_eq!(trace::dump(|entry| entries.push(entry.buffer_num)), 3);
+    assert_eq!(entries, (3..trace::LEN as u32 + 3).collect::<Vec<_>>());
+    trace::clear();
+    assert_eq!(trace::dump(|_| panic!("the trace was cleared")), 0);
+}
+
+// The same workload on two implementations: share a buffer, take it back
+// twice, and drop it.
+#[test]
+fn counts_redundant_unshares() {
+    fake_kernel::reset();
+    {
+        let mut buffer = pin!(no_dynamic::Buffer::<Rw, [u8; 4]>::from([0; 4]));
+        assert_eq!(buffer.as_mut().allow(), Ok(()));
+        buffer.as_ref().buffer();
+        buffer.as_ref().buffer();
+    }
+    let no_dynamic = entries();
+    assert!(no_dynamic[0].kind == Kind::StaticAllow);
+    #[cfg(not(feature = "registry"))]
+    assert_eq!(trace::redundant_unshares(&no_dynamic), 2);
+    #[cfg(feature = "registry")]
+    assert_eq!(trace::redundant_unshares(&no_dynamic), 0);
+
+    fake_kernel::reset();
+    {
+        let mut buffer = pin!(full_dynamic::Buffer::from([0u8; 4]));
+        assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 2), Ok(()));
+        buffer.as_mut().unallow();
+        buffer.as_mut().unallow();
+    }
+    assert_eq!(trace::redundant_unshares(&entries()), 0);
+}
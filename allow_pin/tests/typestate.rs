@@ -0,0 +1,142 @@
+use allow_pin::fake_kernel::{self, Shared, Syscall};
+use allow_pin::typestate::*;
+use allow_pin::{DriverNum, DynamicType, ErrorCode};
+use core::cell::Cell;
+use core::pin::pin;
+
+#[test]
+fn share_then_unshare() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowRw<DriverNum<1>, 2>, [u8; 4]>::from([0; 4]));
+    let mut handle = buffer.as_mut().handle();
+    let address = handle.buffer_mut().as_mut_ptr();
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Rw, 1, 2)]
+    );
+    let shared = handle.share().map_err(|(_, error)| error).unwrap();
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 2),
+        Some(Shared { address, len: 4 })
+    );
+    let handle = shared.unshare();
+    assert_eq!(*handle.buffer(), [0; 4]);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [
+            Syscall::Allow {
+                allow_type: DynamicType::Rw,
+                driver_num: 1,
+                buffer_num: 2,
+                address,
+                len: 4,
+            },
+            Syscall::unallow(DynamicType::Rw, 1, 2),
+        ]
+    );
+}
+
+#[test]
+fn guard_unallows_on_drop() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowRo<DriverNum<1>, 1>, [u8; 2]>::from(*b"hi"));
+    {
+        let shared = buffer.as_mut().handle().share().ok().unwrap();
+        assert_eq!(shared.buffer(), b"hi");
+        fake_kernel::take_log();
+    }
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Ro, 1, 1)]
+    );
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn failed_share_returns_handle() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([3; 2]));
+    let handle = buffer.as_mut().handle();
+    fake_kernel::fail_next(ErrorCode::NoDevice);
+    let Err((handle, error)) = handle.share() else {
+        panic!("share should fail");
+    };
+    assert_eq!(error, ErrorCode::NoDevice);
+    assert_eq!(*handle.buffer(), [3; 2]);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn leaked_guard_is_unallowed_by_next_handle() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([0; 2]));
+    core::mem::forget(buffer.as_mut().handle().share().ok().unwrap());
+    assert!(fake_kernel::shared(DynamicType::Rw, 1, 0).is_some());
+    let _handle = buffer.as_mut().handle();
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 0), None);
+}
+
+#[test]
+fn replace_with() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([1; 2]));
+    let mut b = pin!(Buffer::<AllowRw<DriverNum<1>, 0>, [u8; 2]>::from([2; 2]));
+    let a = a.as_mut().handle();
+    let mut b = b.as_mut().handle();
+    let b_address = b.buffer_mut().as_mut_ptr();
+    let shared = a.share().ok().unwrap();
+    fake_kernel::take_log();
+    let (a, shared) = shared.replace_with(b).ok().unwrap();
+    assert_eq!(*a.buffer(), [1; 2]);
+    assert_eq!(
+        fake_kernel::shared(DynamicType::Rw, 1, 0),
+        Some(Shared {
+            address: b_address,
+            len: 2
+        })
+    );
+    assert_eq!(fake_kernel::take_log().len(), 1);
+    drop(shared);
+    assert_eq!(fake_kernel::allow_table(), []);
+}
+
+#[test]
+fn userspace_readable_guard() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowUserspaceReadable<DriverNum<3>, 0>, [u8; 2]>::from([0; 2]));
+    let mut shared = buffer.as_mut().handle().share().ok().unwrap();
+    assert!(fake_kernel::write(
+        DynamicType::UserspaceReadable,
+        3,
+        0,
+        0,
+        &[4, 2]
+    ));
+    assert_eq!(shared.read(), Some([4, 2]));
+    let mut second = [0];
+    shared.read_volatile(1, &mut second);
+    assert_eq!(second, [2]);
+}
+
+#[test]
+fn subscription() {
+    fake_kernel::reset();
+    let mut done = pin!(Subscription::<Cell<Option<[u32; 3]>>, 1, 1>::default());
+    {
+        let subscribed = done.as_mut().subscribe().unwrap();
+        assert!(fake_kernel::upcall(1, 1, [1, 2, 3]));
+        assert_eq!(subscribed.upcall().get(), Some([1, 2, 3]));
+        fake_kernel::take_log();
+    }
+    assert_eq!(fake_kernel::take_log(), [Syscall::unsubscribe(1, 1)]);
+    assert!(!fake_kernel::subscribed(1, 1));
+}
+
+#[test]
+#[should_panic(expected = "assertion failed")]
+fn read_volatile_rejects_an_overflowing_offset() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<AllowUserspaceReadable<DriverNum<3>, 0>, [u8; 2]>::from([0; 2]));
+    let mut shared = buffer.as_mut().handle().share().ok().unwrap();
+    shared.read_volatile(usize::MAX, &mut [0; 1]);
+}
@@ -0,0 +1,144 @@
+use allow_pin::fake_kernel::{self, Syscall};
+use allow_pin::uninit::{Buffer, POISON, Subscription};
+use allow_pin::{AllowRw, DriverNum, DynamicType, ErrorCode};
+use core::pin::pin;
+
+type Rw = AllowRw<DriverNum<1>, 2>;
+type Done = Subscription<DriverNum<1>, 0, 1>;
+
+#[test]
+fn unallow_returns_the_reported_prefix() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, 8>::default());
+    let done = pin!(Done::default());
+    assert_eq!(buffer.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, b"abc"));
+    assert_eq!(done.as_ref().received(), None);
+    assert!(fake_kernel::upcall(1, 0, [0, 3, 0]));
+    assert_eq!(done.as_ref().received(), Some(3));
+    fake_kernel::take_log();
+
+    assert_eq!(buffer.as_mut().unallow(done.as_ref()), b"abc");
+    assert_eq!(
+        fake_kernel::take_log(),
+        [Syscall::unallow(DynamicType::Rw, 1, 2)]
+    );
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 2), None);
+    // The report is used up.
+    assert_eq!(done.as_ref().received(), None);
+    assert_eq!(buffer.as_mut().unallow(done.as_ref()), []);
+}
+
+#[test]
+fn reported_length_is_clamped() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, 4>::default());
+    let done = pin!(Done::default());
+    assert_eq!(buffer.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, b"abcd"));
+    assert!(fake_kernel::upcall(1, 0, [0, 100, 0]));
+    assert_eq!(buffer.as_mut().unallow(done.as_ref()), b"abcd");
+}
+
+#[test]
+fn only_the_written_prefix_is_exposed() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, 8>::default());
+    let done = pin!(Done::default());
+    assert_eq!(buffer.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    let shared = fake_kernel::shared(DynamicType::Rw, 1, 2).unwrap();
+    // Until the kernel writes it, the buffer holds the host's stand-in for
+    // uninitialized memory.
+    let before = unsafe { core::slice::from_raw_parts(shared.address, shared.len) }.to_vec();
+    assert_eq!(before, [POISON; 8]);
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, b"ab"));
+    assert!(fake_kernel::upcall(1, 0, [0, 2, 0]));
+    let prefix = buffer.as_mut().unallow(done.as_ref());
+    assert_eq!(prefix, b"ab");
+    assert!(!prefix.contains(&POISON));
+}
+
+#[test]
+fn reports_from_before_the_allow_are_ignored() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, 4>::default());
+    let done = pin!(Done::default());
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    assert!(fake_kernel::upcall(1, 0, [0, 4, 0]));
+    assert_eq!(buffer.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(done.as_ref().received(), None);
+    assert_eq!(buffer.as_mut().unallow(done.as_ref()), []);
+}
+
+#[test]
+fn another_subscription_reports_nothing() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, 4>::default());
+    let done = pin!(Done::default());
+    let other = pin!(Done::default());
+    assert_eq!(buffer.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(other.as_ref().subscribe(), Ok(()));
+    assert!(fake_kernel::upcall(1, 0, [0, 4, 0]));
+    assert_eq!(buffer.as_mut().unallow(other.as_ref()), []);
+    assert_eq!(other.as_ref().received(), Some(4));
+}
+
+#[test]
+fn failed_allow_returns_nothing() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, 4>::default());
+    let done = pin!(Done::default());
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    fake_kernel::fail_next(ErrorCode::NoMem);
+    assert_eq!(buffer.as_mut().allow(done.as_ref()), Err(ErrorCode::NoMem));
+    assert!(fake_kernel::upcall(1, 0, [0, 4, 0]));
+    assert_eq!(buffer.as_mut().unallow(done.as_ref()), []);
+}
+
+#[test]
+fn never_allowed_buffer_returns_nothing() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<Rw, 4>::default());
+    let done = pin!(Done::default());
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    assert!(fake_kernel::upcall(1, 0, [0, 4, 0]));
+    assert_eq!(buffer.as_mut().unallow(done.as_ref()), []);
+}
+
+#[test]
+fn drop_unallows_and_unsubscribes() {
+    fake_kernel::reset();
+    {
+        let mut buffer = pin!(Buffer::<Rw, 4>::default());
+        let done = pin!(Done::default());
+        assert_eq!(buffer.as_mut().allow(done.as_ref()), Ok(()));
+        assert_eq!(done.as_ref().subscribe(), Ok(()));
+        fake_kernel::take_log();
+    }
+    assert_eq!(fake_kernel::shared(DynamicType::Rw, 1, 2), None);
+    assert!(!fake_kernel::upcall(1, 0, [0, 4, 0]));
+    assert_eq!(
+        fake_kernel::take_log(),
+        [
+            Syscall::unsubscribe(1, 0),
+            Syscall::unallow(DynamicType::Rw, 1, 2)
+        ]
+    );
+}
+
+#[test]
+fn a_swapped_out_buffer_ignores_the_report() {
+    fake_kernel::reset();
+    let mut a = pin!(Buffer::<Rw, 4>::default());
+    let mut b = pin!(allow_pin::no_dynamic::Buffer::<Rw, [u8; 4]>::default());
+    let done = pin!(Done::default());
+    assert_eq!(done.as_ref().subscribe(), Ok(()));
+    assert_eq!(a.as_mut().allow(done.as_ref()), Ok(()));
+    assert_eq!(b.as_mut().allow(), Ok(()));
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 2, 0, b"abcd"));
+    assert!(fake_kernel::upcall(1, 0, [0, 4, 0]));
+    assert_eq!(a.as_mut().unallow(done.as_ref()), []);
+}
@@ -0,0 +1,170 @@
+use allow_pin::fake_kernel;
+use allow_pin::full_dynamic::*;
+use allow_pin::view::{View, ViewMut};
+use allow_pin::{Allow, DriverNum, dynamic_type, no_dynamic};
+use core::pin::pin;
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+#[derive(Debug, Eq, FromBytes, Immutable, IntoBytes, KnownLayout, PartialEq)]
+#[repr(C)]
+struct Response {
+    status: u16,
+    len: u16,
+}
+
+// A header that can be written but not read back: `bool` is not `FromBytes`.
+#[derive(Immutable, IntoBytes, KnownLayout)]
+#[repr(C)]
+struct Request {
+    urgent: bool,
+    command: u8,
+}
+
+#[test]
+fn decode_kernel_response() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 8]>::from([0; 8]));
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw, 1, 0), Ok(()));
+    let mut response = Vec::from(Response { status: 1, len: 3 }.as_bytes());
+    response.extend([7, 8, 9]);
+    assert!(fake_kernel::write(DynamicType::Rw, 1, 0, 0, &response));
+    assert_eq!(
+        buffer.as_ref().view::<Response>().err(),
+        Some(ErrorCode::Busy)
+    );
+    buffer.as_mut().unallow();
+    let View { header, payload } = buffer.as_ref().view::<Response>().unwrap();
+    assert_eq!(*header, Response { status: 1, len: 3 });
+    assert_eq!(payload[..header.len as usize], [7, 8, 9]);
+}
+
+#[test]
+fn encode_before_read_only_allow() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 6]>::from([0; 6]));
+    let payload = buffer
+        .as_mut()
+        .write_header(Request {
+            urgent: true,
+            command: 2,
+        })
+        .unwrap();
+    payload[..2].copy_from_slice(b"hi");
+    assert_eq!(buffer.as_mut().allow(DynamicType::Ro, 1, 1), Ok(()));
+    assert_eq!(
+        buffer
+            .as_mut()
+            .write_header(Response { status: 0, len: 0 })
+            .err(),
+        Some(ErrorCode::Busy)
+    );
+    let shared = fake_kernel::shared(DynamicType::Ro, 1, 1).unwrap();
+    let bytes = unsafe { core::slice::from_raw_parts(shared.address, shared.len) };
+    assert_eq!(*bytes, [1, 2, b'h', b'i', 0, 0]);
+}
+
+#[test]
+fn view_mut_edits_an_unshared_buffer() {
+    fake_kernel::reset();
+    let mut buffer = pin!(dynamic_type::Buffer::<Allow<DriverNum<1>, 0>, [u8; 6]>::from([0; 6]));
+    {
+        let ViewMut {
+            mut header,
+            payload,
+        } = buffer.as_mut().view_mut::<Response>().unwrap();
+        header.len = 2;
+        payload[..2].copy_from_slice(b"ok");
+    }
+    assert_eq!(buffer.as_mut().allow(DynamicType::Rw), Ok(()));
+    assert_eq!(
+        buffer.as_mut().view_mut::<Response>().err(),
+        Some(ErrorCode::Busy)
+    );
+    assert_eq!(
+        buffer.as_mut().read_header::<Response>().err(),
+        Some(ErrorCode::Busy)
+    );
+    buffer.as_mut().unallow();
+    assert_eq!(buffer.as_ref().view::<Response>().unwrap().payload, b"ok");
+}
+
+#[test]
+fn read_header_while_userspace_readable() {
+    fake_kernel::reset();
+    let mut buffer = pin!(Buffer::<[u8; 8]>::from([0; 8]));
+    assert_eq!(
+        buffer.as_mut().allow(DynamicType::UserspaceReadable, 2, 0),
+        Ok(())
+    );
+    assert!(fake_kernel::write(
+        DynamicType::UserspaceReadable,
+        2,
+        0,
+        0,
+        Response { status: 4, len: 1 }.as_bytes()
+    ));
+    fake_kernel::take_log();
+    assert_eq!(
+        buffer.as_mut().read_header::<Response>(),
+        Ok(Response { status: 4, len: 1 })
+    );
+    assert_eq!(fake_kernel::take_log(), []);
+    assert!(fake_kernel::shared(DynamicType::UserspaceReadable, 2, 0).is_some());
+}
+
+#[test]
+fn no_dynamic_views_unallow_first() {
+    fake_kernel::reset();
+    let mut buffer = pin!(no_dynamic::Buffer::<
+        no_dynamic::AllowUserspaceReadable<DriverNum<2>, 0>,
+        [u8; 4],
+    >::from([0; 4]));
+    assert_eq!(buffer.as_mut().allow(), Ok(()));
+    assert!(fake_kernel::write(
+        DynamicType::UserspaceReadable,
+        2,
+        0,
+        0,
+        &5u16.to_ne_bytes()
+    ));
+    fake_kernel::take_log();
+    assert_eq!(buffer.as_mut().read_header::<u16>(), Ok(5));
+    assert_eq!(fake_kernel::take_log(), []);
+    assert_eq!(*buffer.as_mut().view::<u16>().unwrap().payload, [0; 2]);
+    assert_eq!(
+        fake_kernel::take_log(),
+        [fake_kernel::Syscall::unallow(
+            DynamicType::UserspaceReadable,
+            2,
+            0
+        )]
+    );
+}
+
+#[test]
+fn length_and_alignment_are_checked() {
+    fake_kernel::reset();
+    let mut short = pin!(Buffer::<[u8; 3]>::from([0; 3]));
+    assert_eq!(short.as_ref().view::<u32>().err(), Some(ErrorCode::Size));
+    assert_eq!(
+        short
+            .as_mut()
+            .write_header(Response { status: 0, len: 0 })
+            .err(),
+        Some(ErrorCode::Size)
+    );
+    assert_eq!(short.as_mut().read_header::<u32>(), Err(ErrorCode::Size));
+    // `[u32; 2]` is always aligned for a `u32` header; `[u8; 8]` is only
+    // aligned by chance, so a misaligned one fails rather than faulting.
+    let mut words = pin!(Buffer::<[u32; 2]>::from([0; 2]));
+    assert_eq!(words.as_mut().write_header(5u32).map(|p| p.len()), Ok(4));
+    assert_eq!(*words.as_ref().view::<u32>().unwrap().header, 5);
+    let bytes = pin!(Buffer::<[u8; 8]>::from([0; 8]));
+    let aligned = (bytes.as_ref().buffer().unwrap().as_ptr() as usize).is_multiple_of(4);
+    let expected = if aligned {
+        None
+    } else {
+        Some(ErrorCode::Invalid)
+    };
+    assert_eq!(bytes.as_ref().view::<u32>().err(), expected);
+}
@@ -0,0 +1,402 @@
+//! Reads section and symbol sizes directly out of ELF files and formats them as
+//! markdown tables, so the size comparisons in this repository do not need a
+//! cross binutils install (`arm-none-eabi-size`, `riscv64-unknown-elf-objdump`,
+//! etc.).
+
+use object::elf::{SHF_ALLOC, SHF_WRITE};
+use object::{Object, ObjectSection, ObjectSymbol, SectionFlags, SectionKind, SymbolKind};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Which part of memory a section occupies, as in the columns printed by
+/// binutils' `size`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// Read-only, stored in flash (code and constants).
+    Text,
+    /// Initialized and writable: stored in flash and copied to RAM.
+    Data,
+    /// Zero-initialized RAM, which takes no space in the image.
+    Bss,
+}
+
+/// An allocated section of an ELF file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub kind: Kind,
+    pub size: u64,
+}
+
+/// The total size of every function or data symbol with a given (demangled)
+/// name. Generic functions are demangled without their type parameters, so
+/// several monomorphizations can share a name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub size: u64,
+    pub copies: u32,
+}
+
+/// The sizes of an ELF file's allocated sections and symbols.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Sizes {
+    /// In address order.
+    pub sections: Vec<Section>,
+    pub symbols: BTreeMap<String, Symbol>,
+}
+
+impl Sizes {
+    pub fn parse(data: &[u8]) -> Result<Sizes, object::Error> {
+        let file = object::File::parse(data)?;
+        let mut sections: Vec<_> = file
+            .sections()
+            .filter_map(|section| {
+                let SectionFlags::Elf { sh_flags } = section.flags() else {
+                    return None;
+                };
+                if sh_flags & u64::from(SHF_ALLOC) == 0 || section.size() == 0 {
+                    return None;
+                }
+                let kind = match section.kind() {
+                    SectionKind::UninitializedData | SectionKind::UninitializedTls => Kind::Bss,
+                    _ if sh_flags & u64::from(SHF_WRITE) != 0 => Kind::Data,
+                    _ => Kind::Text,
+                };
+                Some((
+                    section.address(),
+                    Section {
+                        name: section.name().ok()?.to_owned(),
+                        kind,
+                        size: section.size(),
+                    },
+                ))
+            })
+            .collect();
+        sections.sort_by_key(|&(address, _)| address);
+        let mut symbols = BTreeMap::<String, Symbol>::new();
+        for symbol in file.symbols() {
+            if !matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data)
+                || !symbol.is_definition()
+                || symbol.size() == 0
+            {
+                continue;
+            }
+            let Ok(name) = symbol.name() else { continue };
+            let name = format!("{:#}", rustc_demangle::demangle(name));
+            let entry = symbols.entry(name).or_insert(Symbol { size: 0, copies: 0 });
+            entry.size += symbol.size();
+            entry.copies += 1;
+        }
+        Ok(Sizes {
+            sections: sections.into_iter().map(|(_, section)| section).collect(),
+            symbols,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Sizes, Box<dyn Error>> {
+        let data = std::fs::read(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        Sizes::parse(&data).map_err(|error| format!("{}: {error}", path.display()).into())
+    }
+
+    /// The total size of the sections of the given kind.
+    pub fn total(&self, kind: Kind) -> u64 {
+        self.sections
+            .iter()
+            .filter(|section| section.kind == kind)
+            .map(|section| section.size)
+            .sum()
+    }
+
+    /// Flash used: code, constants, and the initial values of `.data`.
+    pub fn flash(&self) -> u64 {
+        self.total(Kind::Text) + self.total(Kind::Data)
+    }
+
+    /// RAM used by statics (the stack and heap are not included).
+    pub fn ram(&self) -> u64 {
+        self.total(Kind::Data) + self.total(Kind::Bss)
+    }
+}
+
+/// Finds the example binaries under a cargo target directory for one target
+/// triple (e.g. `target/thumbv6m-none-eabi`), returning the path of each
+/// example for each profile (`opt-z`, `release`, ...). Dependency files and
+/// the hash-suffixed copies cargo keeps are skipped.
+pub fn find_examples(
+    dir: &Path,
+) -> Result<BTreeMap<String, BTreeMap<String, PathBuf>>, Box<dyn Error>> {
+    let mut examples = BTreeMap::<_, BTreeMap<_, _>>::new();
+    for profile in std::fs::read_dir(dir).map_err(|error| format!("{}: {error}", dir.display()))? {
+        let profile = profile?;
+        let Ok(entries) = std::fs::read_dir(profile.path().join("examples")) else {
+            continue;
+        };
+        let profile = profile.file_name().to_string_lossy().into_owned();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.contains(['.', '-']) || !entry.file_type()?.is_file() {
+                continue;
+            }
+            examples
+                .entry(name)
+                .or_default()
+                .insert(profile.clone(), entry.path());
+        }
+    }
+    Ok(examples)
+}
+
+/// A markdown table whose columns are padded to line up, like the tables in
+/// size_comparison/README.md.
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<S: ToString>(header: impl IntoIterator<Item = S>) -> Table {
+        Table {
+            header: header.into_iter().map(|cell| cell.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row<S: ToString>(&mut self, row: impl IntoIterator<Item = S>) {
+        self.rows
+            .push(row.into_iter().map(|cell| cell.to_string()).collect());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut widths: Vec<_> = self.header.iter().map(String::len).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let line = |f: &mut std::fmt::Formatter, cells: &[String]| {
+            for (cell, width) in cells.iter().zip(&widths) {
+                write!(f, "| {cell:width$} ")?;
+            }
+            writeln!(f, "|")
+        };
+        line(f, &self.header)?;
+        let rules: Vec<_> = widths.iter().map(|&width| "-".repeat(width)).collect();
+        line(f, &rules)?;
+        for row in &self.rows {
+            line(f, row)?;
+        }
+        Ok(())
+    }
+}
+
+/// A table of section sizes with one column per file. Sections that are
+/// missing from a file count as 0 bytes.
+pub fn section_table(files: &[(&str, &Sizes)]) -> Table {
+    let mut table = Table::new(
+        ["Section"]
+            .into_iter()
+            .chain(files.iter().map(|&(label, _)| label)),
+    );
+    for name in section_names(files.iter().map(|&(_, sizes)| sizes)) {
+        table.row(
+            [format!("`{name}`")].into_iter().chain(
+                files
+                    .iter()
+                    .map(|(_, sizes)| section_size(sizes, name).to_string()),
+            ),
+        );
+    }
+    table
+}
+
+/// A table of symbol sizes, largest first.
+pub fn symbol_table(sizes: &Sizes) -> Table {
+    let mut symbols: Vec<_> = sizes.symbols.iter().collect();
+    symbols.sort_by(|(a_name, a), (b_name, b)| b.size.cmp(&a.size).then(a_name.cmp(b_name)));
+    let mut table = Table::new(["Symbol", "Size"]);
+    for (name, symbol) in symbols {
+        table.row([symbol_name(name, symbol), symbol.size.to_string()]);
+    }
+    table
+}
+
+/// Compares two files: a table of section sizes, followed by the symbols that
+/// are unchanged, removed or shrunk, grown, and new, in the layout of
+/// size_comparison/README.md.
+pub fn diff(old_label: &str, old: &Sizes, new_label: &str, new: &Sizes) -> String {
+    let mut sections = Table::new(["Section", old_label, new_label, "Change"]);
+    for name in section_names([old, new]) {
+        let (old_size, new_size) = (section_size(old, name), section_size(new, name));
+        sections.row([
+            format!("`{name}`"),
+            old_size.to_string(),
+            new_size.to_string(),
+            change(old_size, new_size),
+        ]);
+    }
+
+    let mut unchanged = Table::new(["Symbol", "Size"]);
+    let mut shrunk = Table::new(["Symbol", old_label, new_label, "Change"]);
+    let mut grown = Table::new(["Symbol", old_label, new_label, "Change"]);
+    let mut added = Table::new(["Symbol", "Size"]);
+    for (name, old_symbol) in &old.symbols {
+        let Some(new_symbol) = new.symbols.get(name) else {
+            shrunk.row([
+                symbol_name(name, old_symbol),
+                old_symbol.size.to_string(),
+                "-".into(),
+                change(old_symbol.size, 0),
+            ]);
+            continue;
+        };
+        let row = [
+            symbol_name(name, new_symbol),
+            old_symbol.size.to_string(),
+            new_symbol.size.to_string(),
+            change(old_symbol.size, new_symbol.size),
+        ];
+        match new_symbol.size.cmp(&old_symbol.size) {
+            std::cmp::Ordering::Less => shrunk.row(row),
+            std::cmp::Ordering::Equal => unchanged.row([row[0].clone(), row[1].clone()]),
+            std::cmp::Ordering::Greater => grown.row(row),
+        }
+    }
+    for (name, new_symbol) in &new.symbols {
+        if !old.symbols.contains_key(name) {
+            added.row([symbol_name(name, new_symbol), new_symbol.size.to_string()]);
+        }
+    }
+
+    let mut out = sections.to_string();
+    for (heading, table) in [
+        ("Unchanged symbols", unchanged),
+        ("Removed/shrunk symbols", shrunk),
+        ("Growing symbols", grown),
+        ("New symbols", added),
+    ] {
+        if !table.is_empty() {
+            write!(out, "\n#### {heading}\n\n{table}").unwrap();
+        }
+    }
+    out
+}
+
+/// Compares two implementations across examples: one row per example and
+/// profile, with the flash and RAM use of each implementation.
+pub fn comparison_table(
+    old_label: &str,
+    new_label: &str,
+    rows: &[(&str, &str, Sizes, Sizes)],
+) -> Table {
+    let mut table = Table::new([
+        "Example".to_owned(),
+        "Profile".to_owned(),
+        format!("{old_label} flash"),
+        format!("{new_label} flash"),
+        "Change".to_owned(),
+        format!("{old_label} RAM"),
+        format!("{new_label} RAM"),
+        "Change".to_owned(),
+    ]);
+    for (example, profile, old, new) in rows {
+        table.row([
+            example.to_string(),
+            profile.to_string(),
+            old.flash().to_string(),
+            new.flash().to_string(),
+            change(old.flash(), new.flash()),
+            old.ram().to_string(),
+            new.ram().to_string(),
+            change(old.ram(), new.ram()),
+        ]);
+    }
+    table
+}
+
+/// Tabulates how flash use scales with the number of call sites. `points` holds
+/// the sizes of each implementation, in order, for a profile and number of call
+/// sites, sorted by profile and then count. Each profile's rows are followed by
+/// the average flash cost of each call site past its smallest count.
+pub fn scaling_table(implementations: &[&str], points: &[(&str, u64, Vec<Sizes>)]) -> Table {
+    let mut table = Table::new(
+        ["Profile", "Call sites"]
+            .into_iter()
+            .chain(implementations.iter().copied()),
+    );
+    let mut first = 0;
+    for (i, (profile, count, sizes)) in points.iter().enumerate() {
+        table.row(
+            [profile.to_string(), count.to_string()]
+                .into_iter()
+                .chain(sizes.iter().map(|sizes| sizes.flash().to_string())),
+        );
+        if points.get(i + 1).is_some_and(|next| next.0 == *profile) {
+            continue;
+        }
+        let (_, first_count, first_sizes) = &points[first];
+        if i != first {
+            let per_site = sizes.iter().zip(first_sizes).map(|(last, first)| {
+                let growth = last.flash() as f64 - first.flash() as f64;
+                format!("{:.1}", growth / (count - first_count) as f64)
+            });
+            table.row(
+                [profile.to_string(), "Per call site".to_owned()]
+                    .into_iter()
+                    .chain(per_site),
+            );
+        }
+        first = i + 1;
+    }
+    table
+}
+
+/// The names of every section in `files`, in order of first appearance.
+fn section_names<'s>(files: impl IntoIterator<Item = &'s Sizes>) -> Vec<&'s str> {
+    let mut names: Vec<&str> = Vec::new();
+    for sizes in files {
+        for section in &sizes.sections {
+            if !names.contains(&section.name.as_str()) {
+                names.push(&section.name);
+            }
+        }
+    }
+    names
+}
+
+fn section_size(sizes: &Sizes, name: &str) -> u64 {
+    sizes
+        .sections
+        .iter()
+        .filter(|section| section.name == name)
+        .map(|section| section.size)
+        .sum()
+}
+
+fn symbol_name(name: &str, symbol: &Symbol) -> String {
+    match symbol.copies {
+        1 => format!("`{name}`"),
+        copies => format!("`{name}` (x{copies})"),
+    }
+}
+
+/// Formats the difference between two sizes with an explicit sign, and a
+/// percentage if `old` is not 0.
+fn change(old: u64, new: u64) -> String {
+    let difference = new as i64 - old as i64;
+    match old {
+        0 => format!("{difference:+}"),
+        _ => format!(
+            "{difference:+} ({:+.1}%)",
+            difference as f64 * 100.0 / old as f64
+        ),
+    }
+}
@@ -0,0 +1,247 @@
+//! Command-line front end for the size report library. Every command prints
+//! markdown to stdout.
+
+use size_report::{
+    Sizes, comparison_table, diff, find_examples, scaling_table, section_table, symbol_table,
+};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+  size_report sections ELF...
+      Section sizes of each ELF, one column per file name.
+  size_report symbols ELF...
+      Symbol sizes of each ELF, largest first.
+  size_report examples DIR [--symbols]
+      Section sizes (and with --symbols, symbol sizes) of every example under
+      DIR, a target directory for one triple (e.g. target/thumbv6m-none-eabi),
+      one column per profile.
+  size_report diff OLD NEW [OLD_LABEL NEW_LABEL]
+      Section sizes and unchanged, shrunk, grown and new symbols between two
+      ELFs.
+  size_report compare DIR OLD_IMPL NEW_IMPL...
+      Flash and RAM use of every example `<name>_<OLD_IMPL>` under DIR against
+      `<name>_<NEW_IMPL>`, at every profile, with one table per NEW_IMPL (e.g.
+      no_dynamic full_dynamic typestate).
+  size_report builds OLD_DIR NEW_DIR OLD_LABEL NEW_LABEL IMPL
+      Flash and RAM use of every example `<name>_<IMPL>` built into both
+      OLD_DIR and NEW_DIR (e.g. without and with a feature), at every profile.
+  size_report scaling DIR FAMILY IMPL...
+      Flash use of the examples `<FAMILY>_<N>_<IMPL>` under DIR, with one
+      column per IMPL and one row per profile and N, and the average cost of
+      each call site (e.g. scaling no_dynamic full_dynamic).
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["sections", elves @ ..] if !elves.is_empty() => sections(elves),
+        ["symbols", elves @ ..] if !elves.is_empty() => symbols(elves),
+        ["examples", dir] => examples(dir, false),
+        ["examples", dir, "--symbols"] => examples(dir, true),
+        ["diff", old, new] => diff_files(old, new, label(old), label(new)),
+        ["diff", old, new, old_label, new_label] => diff_files(old, new, old_label, new_label),
+        ["compare", dir, old, news @ ..] if !news.is_empty() => compare(dir, old, news),
+        [
+            "builds",
+            old_dir,
+            new_dir,
+            old_label,
+            new_label,
+            implementation,
+        ] => builds(old_dir, new_dir, old_label, new_label, implementation),
+        ["scaling", dir, family, implementations @ ..] if !implementations.is_empty() => {
+            scaling(dir, family, implementations)
+        }
+        _ => {
+            eprint!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("size_report: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn sections(elves: &[&str]) -> Result<(), Box<dyn Error>> {
+    let sizes = read_all(elves)?;
+    let files: Vec<_> = elves.iter().map(|elf| label(elf)).zip(&sizes).collect();
+    print!("{}", section_table(&files));
+    Ok(())
+}
+
+fn symbols(elves: &[&str]) -> Result<(), Box<dyn Error>> {
+    for (i, elf) in elves.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "\n" };
+        print!("{separator}### {elf}\n\n{}", symbol_table(&read(elf)?));
+    }
+    Ok(())
+}
+
+fn examples(dir: &str, with_symbols: bool) -> Result<(), Box<dyn Error>> {
+    for (i, (example, profiles)) in find_examples(Path::new(dir))?.iter().enumerate() {
+        let sizes = profiles
+            .values()
+            .map(|path| Sizes::read(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let files: Vec<_> = profiles.keys().map(String::as_str).zip(&sizes).collect();
+        let separator = if i == 0 { "" } else { "\n" };
+        print!("{separator}### {example}\n\n{}", section_table(&files));
+        if with_symbols {
+            for (profile, sizes) in files {
+                print!("\n#### {example} ({profile})\n\n{}", symbol_table(sizes));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn diff_files(
+    old: &str,
+    new: &str,
+    old_label: &str,
+    new_label: &str,
+) -> Result<(), Box<dyn Error>> {
+    print!("{}", diff(old_label, &read(old)?, new_label, &read(new)?));
+    Ok(())
+}
+
+fn compare(dir: &str, old: &str, news: &[&str]) -> Result<(), Box<dyn Error>> {
+    let examples = find_examples(Path::new(dir))?;
+    for (i, new) in news.iter().enumerate() {
+        let mut rows = Vec::new();
+        for (name, old_profiles) in &examples {
+            let Some(scenario) = name.strip_suffix(&format!("_{old}")) else {
+                continue;
+            };
+            let Some(new_profiles) = examples.get(&format!("{scenario}_{new}")) else {
+                continue;
+            };
+            for (profile, old_path) in old_profiles {
+                if let Some(new_path) = new_profiles.get(profile) {
+                    rows.push((
+                        scenario,
+                        profile.as_str(),
+                        Sizes::read(old_path)?,
+                        Sizes::read(new_path)?,
+                    ));
+                }
+            }
+        }
+        if rows.is_empty() {
+            return Err(format!("no examples in {dir} are built for both {old} and {new}").into());
+        }
+        let separator = if i == 0 { "" } else { "\n" };
+        print!(
+            "{separator}### {old} vs {new}\n\n{}",
+            comparison_table(old, new, &rows)
+        );
+    }
+    Ok(())
+}
+
+fn builds(
+    old_dir: &str,
+    new_dir: &str,
+    old_label: &str,
+    new_label: &str,
+    implementation: &str,
+) -> Result<(), Box<dyn Error>> {
+    let old_examples = find_examples(Path::new(old_dir))?;
+    let new_examples = find_examples(Path::new(new_dir))?;
+    let mut rows = Vec::new();
+    for (name, old_profiles) in &old_examples {
+        let Some(scenario) = name.strip_suffix(&format!("_{implementation}")) else {
+            continue;
+        };
+        let Some(new_profiles) = new_examples.get(name) else {
+            continue;
+        };
+        for (profile, old_path) in old_profiles {
+            if let Some(new_path) = new_profiles.get(profile) {
+                rows.push((
+                    scenario,
+                    profile.as_str(),
+                    Sizes::read(old_path)?,
+                    Sizes::read(new_path)?,
+                ));
+            }
+        }
+    }
+    if rows.is_empty() {
+        return Err(format!(
+            "no {implementation} examples are built in both {old_dir} and {new_dir}"
+        )
+        .into());
+    }
+    print!("{}", comparison_table(old_label, new_label, &rows));
+    Ok(())
+}
+
+fn scaling(dir: &str, family: &str, implementations: &[&str]) -> Result<(), Box<dyn Error>> {
+    // The path of each implementation's example, by profile and call sites.
+    let mut paths = BTreeMap::<_, BTreeMap<_, _>>::new();
+    for (name, profiles) in find_examples(Path::new(dir))? {
+        let Some((count, implementation)) = name
+            .strip_prefix(&format!("{family}_"))
+            .and_then(|rest| rest.split_once('_'))
+        else {
+            continue;
+        };
+        let Ok(count) = count.parse::<u64>() else {
+            continue;
+        };
+        for (profile, path) in profiles {
+            paths
+                .entry((profile, count))
+                .or_default()
+                .insert(implementation.to_owned(), path);
+        }
+    }
+    let mut points = Vec::new();
+    for ((profile, count), paths) in &paths {
+        let Some(paths) = implementations
+            .iter()
+            .map(|implementation| paths.get(*implementation))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let sizes = paths
+            .into_iter()
+            .map(|path| Sizes::read(path))
+            .collect::<Result<_, _>>()?;
+        points.push((profile.as_str(), *count, sizes));
+    }
+    if points.is_empty() {
+        return Err(
+            format!("no {family} examples in {dir} are built for every implementation").into(),
+        );
+    }
+    print!("{}", scaling_table(implementations, &points));
+    Ok(())
+}
+
+fn read(elf: &str) -> Result<Sizes, Box<dyn Error>> {
+    Sizes::read(Path::new(elf))
+}
+
+fn read_all(elves: &[&str]) -> Result<Vec<Sizes>, Box<dyn Error>> {
+    elves.iter().map(|elf| read(elf)).collect()
+}
+
+/// The default label for a file: its file name.
+fn label(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
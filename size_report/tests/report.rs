@@ -0,0 +1,193 @@
+use object::write::{Object, Symbol as WriteSymbol, SymbolSection};
+use object::{
+    Architecture, BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolKind, SymbolScope,
+};
+use size_report::{
+    Kind, Section, Sizes, Symbol, diff, find_examples, scaling_table, section_table,
+};
+
+// Builds an ARM ELF with the given functions (name, size) in `.text`, and
+// `data` and `bss` bytes of writable statics.
+fn elf(functions: &[(&str, usize)], data: usize, bss: u64) -> Vec<u8> {
+    let mut object = Object::new(BinaryFormat::Elf, Architecture::Arm, Endianness::Little);
+    let text = object.add_section(Vec::new(), b".text".to_vec(), SectionKind::Text);
+    for &(name, size) in functions {
+        let symbol = object.add_symbol(WriteSymbol {
+            name: name.as_bytes().to_vec(),
+            value: 0,
+            size: 0,
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: SymbolSection::Undefined,
+            flags: SymbolFlags::None,
+        });
+        object.add_symbol_data(symbol, text, &vec![0; size], 2);
+    }
+    if data > 0 {
+        let section = object.add_section(Vec::new(), b".data".to_vec(), SectionKind::Data);
+        object.append_section_data(section, &vec![0; data], 4);
+    }
+    if bss > 0 {
+        let section =
+            object.add_section(Vec::new(), b".bss".to_vec(), SectionKind::UninitializedData);
+        object.append_section_bss(section, bss, 4);
+    }
+    object.write().unwrap()
+}
+
+#[test]
+fn sections_and_symbols() {
+    let sizes = Sizes::parse(&elf(
+        &[
+            ("_start", 40),
+            ("_ZN4core3ptr13drop_in_place17h0123456789abcdefE", 6),
+            ("_ZN4core3ptr13drop_in_place17hfedcba9876543210E", 10),
+        ],
+        8,
+        24,
+    ))
+    .unwrap();
+    assert_eq!(
+        sizes.sections,
+        [
+            Section {
+                name: ".text".into(),
+                kind: Kind::Text,
+                size: 56
+            },
+            Section {
+                name: ".data".into(),
+                kind: Kind::Data,
+                size: 8
+            },
+            Section {
+                name: ".bss".into(),
+                kind: Kind::Bss,
+                size: 24
+            },
+        ]
+    );
+    assert_eq!((sizes.flash(), sizes.ram()), (64, 32));
+    assert_eq!(
+        sizes.symbols.into_iter().collect::<Vec<_>>(),
+        [
+            (
+                "_start".into(),
+                Symbol {
+                    size: 40,
+                    copies: 1
+                }
+            ),
+            (
+                "core::ptr::drop_in_place".into(),
+                Symbol {
+                    size: 16,
+                    copies: 2
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn tables() {
+    let old = Sizes::parse(&elf(
+        &[("main", 116), ("start", 60), ("interrupt", 56)],
+        0,
+        8,
+    ))
+    .unwrap();
+    let new = Sizes::parse(&elf(&[("main", 172), ("start", 60), ("poll", 360)], 80, 32)).unwrap();
+    assert_eq!(
+        section_table(&[("No Futures", &old), ("Futures", &new)]).to_string(),
+        "\
+| Section | No Futures | Futures |
+| ------- | ---------- | ------- |
+| `.text` | 232        | 592     |
+| `.bss`  | 8          | 32      |
+| `.data` | 0          | 80      |
+"
+    );
+    assert_eq!(
+        diff("No Futures", &old, "Futures", &new),
+        "\
+| Section | No Futures | Futures | Change         |
+| ------- | ---------- | ------- | -------------- |
+| `.text` | 232        | 592     | +360 (+155.2%) |
+| `.bss`  | 8          | 32      | +24 (+300.0%)  |
+| `.data` | 0          | 80      | +80            |
+
+#### Unchanged symbols
+
+| Symbol  | Size |
+| ------- | ---- |
+| `start` | 60   |
+
+#### Removed/shrunk symbols
+
+| Symbol      | No Futures | Futures | Change        |
+| ----------- | ---------- | ------- | ------------- |
+| `interrupt` | 56         | -       | -56 (-100.0%) |
+
+#### Growing symbols
+
+| Symbol | No Futures | Futures | Change       |
+| ------ | ---------- | ------- | ------------ |
+| `main` | 116        | 172     | +56 (+48.3%) |
+
+#### New symbols
+
+| Symbol | Size |
+| ------ | ---- |
+| `poll` | 360  |
+"
+    );
+}
+
+#[test]
+fn examples_are_found_per_profile() {
+    let dir = std::env::temp_dir().join(format!("size_report_{}", std::process::id()));
+    for profile in ["opt-s", "opt-z"] {
+        let examples = dir.join(profile).join("examples");
+        std::fs::create_dir_all(&examples).unwrap();
+        for file in [
+            "swap_no_dynamic",
+            "swap_no_dynamic.d",
+            "swap_no_dynamic-0123abcd",
+        ] {
+            std::fs::write(examples.join(file), []).unwrap();
+        }
+    }
+    std::fs::create_dir_all(dir.join("build")).unwrap();
+    let examples = find_examples(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(examples.len(), 1);
+    assert_eq!(
+        examples["swap_no_dynamic"].keys().collect::<Vec<_>>(),
+        ["opt-s", "opt-z"]
+    );
+}
+
+#[test]
+fn scaling() {
+    let sizes = |text| Sizes::parse(&elf(&[("_start", text)], 0, 0)).unwrap();
+    let points = [
+        ("opt-s", 10, vec![sizes(200), sizes(300)]),
+        ("opt-s", 100, vec![sizes(1100), sizes(1200)]),
+        ("opt-s", 1000, vec![sizes(10100), sizes(12300)]),
+        ("opt-z", 10, vec![sizes(150), sizes(250)]),
+    ];
+    assert_eq!(
+        scaling_table(&["no_dynamic", "full_dynamic"], &points).to_string(),
+        "\
+| Profile | Call sites    | no_dynamic | full_dynamic |
+| ------- | ------------- | ---------- | ------------ |
+| opt-s   | 10            | 200        | 300          |
+| opt-s   | 100           | 1100       | 1200         |
+| opt-s   | 1000          | 10100      | 12300        |
+| opt-s   | Per call site | 10.0       | 12.1         |
+| opt-z   | 10            | 150        | 250          |
+"
+    );
+}